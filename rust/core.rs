@@ -0,0 +1,811 @@
+use crate::error::Error;
+use crate::numeric::{self, Number};
+use crate::reader::{self, Arity, Embedded, Pattern};
+use crate::reader_macros::ReaderConfig;
+use crate::value::{Value, ValueMap, ValueSet};
+use regex::Captures;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fs;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn numbers(args: &[Value]) -> Result<Vec<Number>, Error> {
+    args.iter().map(Value::to_number).collect()
+}
+
+fn arithmetic(
+    args: Vec<Value>,
+    identity: i64,
+    op: fn(Number, Number) -> Result<Number, Error>,
+) -> Result<Value, Error> {
+    Ok(numeric::fold(Number::Int(identity), &numbers(&args)?, op)?.into())
+}
+
+fn comparison(args: Vec<Value>, test: fn(Ordering) -> bool) -> Result<Value, Error> {
+    Ok(Value::Bool(numeric::compare_chain(&numbers(&args)?, test)?))
+}
+
+/// The elements of a list or vector; `nil` is the empty sequence.
+fn elements<'a>(name: &str, value: &'a Value) -> Result<&'a [Value], Error> {
+    match value {
+        Value::Nil => Ok(&[]),
+        _ => value
+            .as_sequence()
+            .ok_or_else(|| value.type_error(name, "a list")),
+    }
+}
+
+/// A builtin testing its single argument.
+fn predicate(test: fn(&Value) -> bool) -> Value {
+    Value::builtin(Arity::exactly(1), move |args| {
+        Ok(Value::Bool(test(&args[0])))
+    })
+}
+
+/// The entries of a map, taken out of `value` so that they are only copied
+/// when the map is shared; `nil` is the empty map.
+fn entries(name: &str, value: Value) -> Result<Rc<ValueMap>, Error> {
+    match &value {
+        Value::Nil => Ok(Rc::default()),
+        Value::Map(m, _) => Ok(m.clone()),
+        _ => Err(value.type_error(name, "a map")),
+    }
+}
+
+/// The members of a set, taken out of `value` like `entries`; `nil` is
+/// the empty set.
+fn members(name: &str, value: Value) -> Result<Rc<ValueSet>, Error> {
+    match &value {
+        Value::Nil => Ok(Rc::default()),
+        Value::Set(s, _) => Ok(s.clone()),
+        _ => Err(value.type_error(name, "a set")),
+    }
+}
+
+/// How many elements a sequence or set has.
+fn count(name: &str, value: &Value) -> Result<usize, Error> {
+    match value {
+        Value::Set(s, _) => Ok(s.len()),
+        _ => Ok(elements(name, value)?.len()),
+    }
+}
+
+/// `map` with the key-value pairs in `pairs` added, each replacing the
+/// value of an equal key.
+fn assoc(name: &str, mut map: Rc<ValueMap>, pairs: Vec<Value>) -> Result<Value, Error> {
+    if pairs.len() % 2 != 0 {
+        return Err(Error::EvalError(format!(
+            "`{}` expects keys and values in pairs",
+            name
+        )));
+    }
+    #[allow(clippy::mutable_key_type)]
+    let entries = Rc::make_mut(&mut map);
+    let mut pairs = pairs.into_iter();
+    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+        entries.insert(key, value);
+    }
+    Ok(Value::Map(map, None))
+}
+
+fn string<'a>(name: &str, value: &'a Value) -> Result<&'a str, Error> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(value.type_error(name, "a string")),
+    }
+}
+
+fn atom<'a>(name: &str, value: &'a Value) -> Result<&'a RefCell<Value>, Error> {
+    match value {
+        Value::Atom(a) => Ok(a),
+        _ => Err(value.type_error(name, "an atom")),
+    }
+}
+
+/// The first form in `source` as data, or `nil` if there is none.
+pub(crate) fn read_string(source: &str, config: &ReaderConfig) -> Result<Value, Error> {
+    match reader::read(source, config)?.first() {
+        Some(form) => Value::from_ast(form),
+        None => Ok(Value::Nil),
+    }
+}
+
+fn slurp(path: &str) -> Result<Value, Error> {
+    fs::read_to_string(path)
+        .map(Value::String)
+        .map_err(|e| Error::EvalError(format!("could not read `{}`: {}", path, e)))
+}
+
+/// `(swap! atom f args...)` sets the atom to `(f @atom args...)`.
+fn swap(mut args: Vec<Value>) -> Result<Value, Error> {
+    let rest = args.split_off(2);
+    let cell = atom("swap!", &args[0])?;
+    let mut call_args = vec![cell.borrow().clone()];
+    call_args.extend(rest);
+    let new = args[1].apply(call_args)?;
+    *cell.borrow_mut() = new.clone();
+    Ok(new)
+}
+
+/// `(apply f args... list)` calls `f` with `args` followed by the elements
+/// of `list`.
+fn apply(mut args: Vec<Value>) -> Result<Value, Error> {
+    let last = args.pop().unwrap_or(Value::Nil);
+    let f = args.remove(0);
+    args.extend_from_slice(elements("apply", &last)?);
+    f.apply(args)
+}
+
+/// `(seq x)` is the elements of a sequence, or the characters of a string,
+/// as a list; it is `nil` when there are none.
+fn seq(value: &Value) -> Result<Value, Error> {
+    let list = match value {
+        Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+        Value::Set(s, _) => s.iter().cloned().collect(),
+        _ => elements("seq", value)?.to_vec(),
+    };
+    Ok(if list.is_empty() {
+        Value::Nil
+    } else {
+        Value::list(list)
+    })
+}
+
+/// `(conj coll xs...)` adds `xs` where the collection grows fastest: at the
+/// front of a list, one by one, or at the end of a vector. A set gains
+/// those of `xs` it does not have yet.
+fn conj(args: &[Value]) -> Result<Value, Error> {
+    let (coll, xs) = (&args[0], &args[1..]);
+    if let Value::Set(s, _) = coll {
+        let mut set = s.clone();
+        Rc::make_mut(&mut set).extend(xs.iter().cloned());
+        return Ok(Value::Set(set, None));
+    }
+    let items = elements("conj", coll)?;
+    Ok(match coll {
+        Value::Vector(..) => Value::vector(items.iter().chain(xs).cloned().collect()),
+        _ => Value::list(xs.iter().rev().chain(items).cloned().collect()),
+    })
+}
+
+/// A symbol that is different from every symbol `gensym` made before.
+fn gensym() -> Value {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    Value::Symbol(format!(
+        "G__{}",
+        COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+    ))
+}
+
+fn time_ms() -> Value {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Value::Int(elapsed.as_millis() as i64)
+}
+
+fn pattern<'a>(name: &str, value: &'a Value) -> Result<&'a Pattern, Error> {
+    match value {
+        Value::Regex(p) => Ok(p),
+        _ => Err(value.type_error(name, "a regex")),
+    }
+}
+
+/// A match as the `re-` functions return it: the matched text, or if the
+/// regex has groups a vector of it and each group, `nil` where a group did
+/// not take part.
+fn match_value(captures: &Captures) -> Value {
+    let text = |m: regex::Match| Value::String(m.as_str().to_owned());
+    if captures.len() == 1 {
+        return captures.get(0).map_or(Value::Nil, text);
+    }
+    Value::vector(
+        captures
+            .iter()
+            .map(|m| m.map_or(Value::Nil, text))
+            .collect(),
+    )
+}
+
+/// `(re-replace re s replacement)` replaces every match of `re` in `s`.
+/// A string replacement may refer to groups as `$1`; a function is called
+/// with each match and returns the string to put in its place.
+fn re_replace(args: &[Value]) -> Result<Value, Error> {
+    let regex = &pattern("re-replace", &args[0])?.regex;
+    let text = string("re-replace", &args[1])?;
+    if let Value::String(replacement) = &args[2] {
+        let replaced = regex.replace_all(text, replacement.as_str());
+        return Ok(Value::String(replaced.into_owned()));
+    }
+    let mut replaced = String::new();
+    let mut last = 0;
+    for captures in regex.captures_iter(text) {
+        let whole = captures.get(0).expect("every match has group 0");
+        replaced.push_str(&text[last..whole.start()]);
+        match &args[2].apply(vec![match_value(&captures)])? {
+            Value::String(s) => replaced.push_str(s),
+            other => return Err(other.type_error("re-replace", "a string replacement")),
+        }
+        last = whole.end();
+    }
+    replaced.push_str(&text[last..]);
+    Ok(Value::String(replaced))
+}
+
+fn join(args: &[Value], readably: bool, separator: &str) -> String {
+    args.iter()
+        .map(|a| a.pr_str(readably))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The builtin functions, by the name they are bound to.
+pub(crate) fn ns() -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "+",
+            Value::builtin(Arity::at_least(0), |args| arithmetic(args, 0, numeric::add)),
+        ),
+        (
+            "-",
+            Value::builtin(Arity::at_least(1), |args| arithmetic(args, 0, numeric::sub)),
+        ),
+        (
+            "*",
+            Value::builtin(Arity::at_least(0), |args| arithmetic(args, 1, numeric::mul)),
+        ),
+        (
+            "/",
+            Value::builtin(Arity::at_least(1), |args| arithmetic(args, 1, numeric::div)),
+        ),
+        (
+            "mod",
+            Value::builtin(Arity::exactly(2), |args| {
+                arithmetic(args, 0, numeric::modulo)
+            }),
+        ),
+        (
+            "rem",
+            Value::builtin(Arity::exactly(2), |args| arithmetic(args, 0, numeric::rem)),
+        ),
+        (
+            "<",
+            Value::builtin(Arity::at_least(1), |args| {
+                comparison(args, |o| o == Ordering::Less)
+            }),
+        ),
+        (
+            "<=",
+            Value::builtin(Arity::at_least(1), |args| {
+                comparison(args, |o| o != Ordering::Greater)
+            }),
+        ),
+        (
+            ">",
+            Value::builtin(Arity::at_least(1), |args| {
+                comparison(args, |o| o == Ordering::Greater)
+            }),
+        ),
+        (
+            ">=",
+            Value::builtin(Arity::at_least(1), |args| {
+                comparison(args, |o| o != Ordering::Less)
+            }),
+        ),
+        (
+            "=",
+            Value::builtin(Arity::at_least(1), |args| {
+                Ok(Value::Bool(args.windows(2).all(|p| p[0] == p[1])))
+            }),
+        ),
+        (
+            "not",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Bool(!args[0].is_truthy()))
+            }),
+        ),
+        (
+            "list",
+            Value::builtin(Arity::at_least(0), |args| Ok(Value::list(args))),
+        ),
+        ("list?", predicate(|v| matches!(v, Value::List(..)))),
+        ("vector?", predicate(|v| matches!(v, Value::Vector(..)))),
+        ("map?", predicate(|v| matches!(v, Value::Map(..)))),
+        ("set?", predicate(|v| matches!(v, Value::Set(..)))),
+        ("sequential?", predicate(|v| v.as_sequence().is_some())),
+        ("nil?", predicate(|v| matches!(v, Value::Nil))),
+        ("true?", predicate(|v| matches!(v, Value::Bool(true)))),
+        ("false?", predicate(|v| matches!(v, Value::Bool(false)))),
+        ("symbol?", predicate(|v| matches!(v, Value::Symbol(_)))),
+        ("string?", predicate(|v| matches!(v, Value::String(_)))),
+        (
+            "number?",
+            predicate(|v| matches!(v, Value::Int(_) | Value::Float(_))),
+        ),
+        (
+            "fn?",
+            predicate(|v| match v {
+                Value::Builtin(_) => true,
+                Value::Closure(c) => !c.is_macro,
+                _ => false,
+            }),
+        ),
+        ("keyword?", predicate(|v| matches!(v, Value::Keyword(_)))),
+        (
+            "symbol",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Symbol(string("symbol", &args[0])?.to_owned()))
+            }),
+        ),
+        (
+            "keyword",
+            Value::builtin(Arity::exactly(1), |args| match &args[0] {
+                Value::Keyword(_) => Ok(args[0].clone()),
+                other => Ok(Value::Keyword(string("keyword", other)?.to_owned())),
+            }),
+        ),
+        (
+            "vector",
+            Value::builtin(Arity::at_least(0), |args| Ok(Value::vector(args))),
+        ),
+        (
+            "cons",
+            Value::builtin(Arity::exactly(2), |args| {
+                let mut list = vec![args[0].clone()];
+                list.extend_from_slice(elements("cons", &args[1])?);
+                Ok(Value::list(list))
+            }),
+        ),
+        (
+            "concat",
+            Value::builtin(Arity::at_least(0), |args| {
+                let mut list = Vec::new();
+                for arg in &args {
+                    list.extend_from_slice(elements("concat", arg)?);
+                }
+                Ok(Value::list(list))
+            }),
+        ),
+        (
+            "vec",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::vector(elements("vec", &args[0])?.to_vec()))
+            }),
+        ),
+        (
+            "nth",
+            Value::builtin(Arity::exactly(2), |args| {
+                let list = elements("nth", &args[0])?;
+                let index = match &args[1] {
+                    Value::Int(i) => *i,
+                    other => return Err(other.type_error("nth", "an integer index")),
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|i| list.get(i))
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::EvalError(format!(
+                            "`nth` index {} is out of range for {} elements",
+                            index,
+                            list.len()
+                        ))
+                    })
+            }),
+        ),
+        (
+            "first",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(elements("first", &args[0])?
+                    .first()
+                    .cloned()
+                    .unwrap_or(Value::Nil))
+            }),
+        ),
+        (
+            "rest",
+            Value::builtin(Arity::exactly(1), |args| {
+                let list = elements("rest", &args[0])?;
+                Ok(Value::list(list.iter().skip(1).cloned().collect()))
+            }),
+        ),
+        (
+            "conj",
+            Value::builtin(Arity::at_least(1), |args| conj(&args)),
+        ),
+        (
+            "seq",
+            Value::builtin(Arity::exactly(1), |args| seq(&args[0])),
+        ),
+        (
+            "hash-map",
+            Value::builtin(Arity::at_least(0), |args| {
+                assoc("hash-map", Rc::default(), args)
+            }),
+        ),
+        (
+            "hash-set",
+            Value::builtin(Arity::at_least(0), |args| Ok(Value::set(args))),
+        ),
+        (
+            "set",
+            Value::builtin(Arity::exactly(1), |args| match &args[0] {
+                Value::Set(s, _) => Ok(Value::Set(s.clone(), None)),
+                other => Ok(Value::set(elements("set", other)?.to_vec())),
+            }),
+        ),
+        (
+            "disj",
+            Value::builtin(Arity::at_least(1), |mut args| {
+                let keys = args.split_off(1);
+                let mut set = members("disj", args.remove(0))?;
+                #[allow(clippy::mutable_key_type)]
+                let members = Rc::make_mut(&mut set);
+                for key in &keys {
+                    members.remove(key);
+                }
+                Ok(Value::Set(set, None))
+            }),
+        ),
+        (
+            "assoc",
+            Value::builtin(Arity::at_least(1), |mut args| {
+                let pairs = args.split_off(1);
+                assoc("assoc", entries("assoc", args.remove(0))?, pairs)
+            }),
+        ),
+        (
+            "dissoc",
+            Value::builtin(Arity::at_least(1), |mut args| {
+                let keys = args.split_off(1);
+                let mut map = entries("dissoc", args.remove(0))?;
+                #[allow(clippy::mutable_key_type)]
+                let entries = Rc::make_mut(&mut map);
+                for key in &keys {
+                    entries.remove(key);
+                }
+                Ok(Value::Map(map, None))
+            }),
+        ),
+        (
+            "get",
+            Value::builtin(Arity::exactly(2), |mut args| {
+                let key = args.pop().unwrap_or(Value::Nil);
+                let found = match &args[0] {
+                    Value::Set(s, _) => s.get(&key).cloned(),
+                    other => entries("get", other.clone())?.get(&key).cloned(),
+                };
+                Ok(found.unwrap_or(Value::Nil))
+            }),
+        ),
+        (
+            "contains?",
+            Value::builtin(Arity::exactly(2), |mut args| {
+                let key = args.pop().unwrap_or(Value::Nil);
+                Ok(Value::Bool(match &args[0] {
+                    Value::Set(s, _) => s.contains(&key),
+                    other => entries("contains?", other.clone())?.contains_key(&key),
+                }))
+            }),
+        ),
+        (
+            "keys",
+            Value::builtin(Arity::exactly(1), |mut args| {
+                let map = entries("keys", args.remove(0))?;
+                Ok(Value::list(map.keys().cloned().collect()))
+            }),
+        ),
+        (
+            "vals",
+            Value::builtin(Arity::exactly(1), |mut args| {
+                let map = entries("vals", args.remove(0))?;
+                Ok(Value::list(map.values().cloned().collect()))
+            }),
+        ),
+        ("apply", Value::builtin(Arity::at_least(2), apply)),
+        (
+            "map",
+            Value::builtin(Arity::exactly(2), |args| {
+                let results = elements("map", &args[1])?
+                    .iter()
+                    .map(|x| args[0].apply(vec![x.clone()]))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::list(results))
+            }),
+        ),
+        (
+            "throw",
+            Value::builtin(Arity::exactly(1), |mut args| {
+                Err(Error::Thrown(Embedded::new(args.remove(0))))
+            }),
+        ),
+        (
+            "empty?",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Bool(count("empty?", &args[0])? == 0))
+            }),
+        ),
+        (
+            "count",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Int(count("count", &args[0])? as i64))
+            }),
+        ),
+        (
+            "pr-str",
+            Value::builtin(Arity::at_least(0), |args| {
+                Ok(Value::String(join(&args, true, " ")))
+            }),
+        ),
+        (
+            "str",
+            Value::builtin(Arity::at_least(0), |args| {
+                Ok(Value::String(join(&args, false, "")))
+            }),
+        ),
+        (
+            "prn",
+            Value::builtin(Arity::at_least(0), |args| {
+                println!("{}", join(&args, true, " "));
+                Ok(Value::Nil)
+            }),
+        ),
+        (
+            "println",
+            Value::builtin(Arity::at_least(0), |args| {
+                println!("{}", join(&args, false, " "));
+                Ok(Value::Nil)
+            }),
+        ),
+        (
+            "read-string",
+            Value::builtin(Arity::exactly(1), |args| {
+                read_string(string("read-string", &args[0])?, &ReaderConfig::default())
+            }),
+        ),
+        (
+            "re-pattern",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Regex(Pattern::new(string("re-pattern", &args[0])?)?))
+            }),
+        ),
+        (
+            "re-find",
+            Value::builtin(Arity::exactly(2), |args| {
+                let regex = &pattern("re-find", &args[0])?.regex;
+                let text = string("re-find", &args[1])?;
+                Ok(regex.captures(text).map_or(Value::Nil, |c| match_value(&c)))
+            }),
+        ),
+        (
+            "re-matches",
+            Value::builtin(Arity::exactly(2), |args| {
+                let whole = &pattern("re-matches", &args[0])?.whole;
+                let text = string("re-matches", &args[1])?;
+                Ok(whole.captures(text).map_or(Value::Nil, |c| match_value(&c)))
+            }),
+        ),
+        (
+            "re-seq",
+            Value::builtin(Arity::exactly(2), |args| {
+                let regex = &pattern("re-seq", &args[0])?.regex;
+                let text = string("re-seq", &args[1])?;
+                let matches: Vec<Value> =
+                    regex.captures_iter(text).map(|c| match_value(&c)).collect();
+                Ok(if matches.is_empty() {
+                    Value::Nil
+                } else {
+                    Value::list(matches)
+                })
+            }),
+        ),
+        (
+            "re-replace",
+            Value::builtin(Arity::exactly(3), |args| re_replace(&args)),
+        ),
+        (
+            "slurp",
+            Value::builtin(Arity::exactly(1), |args| slurp(string("slurp", &args[0])?)),
+        ),
+        (
+            "macro?",
+            predicate(|v| matches!(v, Value::Closure(c) if c.is_macro)),
+        ),
+        (
+            "meta",
+            Value::builtin(Arity::exactly(1), |args| Ok(args[0].meta())),
+        ),
+        (
+            "with-meta",
+            Value::builtin(Arity::exactly(2), |mut args| {
+                let meta = args.pop().unwrap_or(Value::Nil);
+                args[0].with_meta(meta)
+            }),
+        ),
+        (
+            "gensym",
+            Value::builtin(Arity::exactly(0), |_| Ok(gensym())),
+        ),
+        (
+            "time-ms",
+            Value::builtin(Arity::exactly(0), |_| Ok(time_ms())),
+        ),
+        (
+            "atom",
+            Value::builtin(Arity::exactly(1), |mut args| {
+                Ok(Value::atom(args.remove(0)))
+            }),
+        ),
+        (
+            "atom?",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(Value::Bool(matches!(args[0], Value::Atom(_))))
+            }),
+        ),
+        (
+            "deref",
+            Value::builtin(Arity::exactly(1), |args| {
+                Ok(atom("deref", &args[0])?.borrow().clone())
+            }),
+        ),
+        (
+            "reset!",
+            Value::builtin(Arity::exactly(2), |mut args| {
+                let value = args.remove(1);
+                *atom("reset!", &args[0])?.borrow_mut() = value.clone();
+                Ok(value)
+            }),
+        ),
+        ("swap!", Value::builtin(Arity::at_least(2), swap)),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequence_arguments() {
+        assert_eq!(elements("count", &Value::Nil).map(<[Value]>::len), Ok(0));
+        assert_eq!(
+            elements("count", &Value::Int(1)),
+            Err(Error::EvalError(
+                "`count` expects a list, found integer 1".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn sequence_access() {
+        let ns = ns()
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>();
+        let v = Value::vector(vec![Value::Int(1), Value::Int(2)]);
+        assert_eq!(
+            ns["nth"].apply(vec![v.clone(), Value::Int(1)]),
+            Ok(Value::Int(2))
+        );
+        assert!(ns["nth"].apply(vec![v.clone(), Value::Int(-1)]).is_err());
+        assert_eq!(ns["first"].apply(vec![Value::Nil]), Ok(Value::Nil));
+        assert_eq!(
+            ns["rest"].apply(vec![v]),
+            Ok(Value::list(vec![Value::Int(2)]))
+        );
+    }
+
+    #[test]
+    fn maps() {
+        let k = |s: &str| Value::Keyword(s.to_owned());
+        let map = assoc(
+            "hash-map",
+            Rc::default(),
+            vec![k("a"), Value::Int(1), k("a"), Value::Int(2)],
+        );
+        assert_eq!(map, Ok(Value::map(vec![k("a"), Value::Int(2)])));
+        let shared = entries("assoc", map.unwrap()).unwrap();
+        let updated = assoc("assoc", shared.clone(), vec![k("b"), Value::Nil]).unwrap();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(updated.pr_str(true).len(), "{:a 2 :b nil}".len());
+        assert_eq!(
+            assoc("hash-map", Rc::default(), vec![k("a")]),
+            Err(Error::EvalError(
+                "`hash-map` expects keys and values in pairs".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn regexes() {
+        let s = |s: &str| Value::String(s.to_owned());
+        let log = "GET /a 200\nPOST /b 500";
+        let request = Pattern::new(r"(\w+) /(\w)(x)? (\d+)").unwrap();
+        let digits = Pattern::new(r"\d+").unwrap();
+        assert_eq!(match_value(&digits.regex.captures(log).unwrap()), s("200"));
+        assert_eq!(
+            match_value(&request.regex.captures(log).unwrap()),
+            Value::vector(vec![
+                s("GET /a 200"),
+                s("GET"),
+                s("a"),
+                Value::Nil,
+                s("200")
+            ])
+        );
+        let request = Value::Regex(request);
+        assert_eq!(
+            re_replace(&[request.clone(), s(log), s("$4 $1")]),
+            Ok(s("200 GET\n500 POST"))
+        );
+        let path = Value::builtin(Arity::exactly(1), |args| match &args[0] {
+            Value::Vector(v, _) => Ok(v[2].clone()),
+            _ => Ok(Value::Nil),
+        });
+        assert_eq!(re_replace(&[request, s(log), path]), Ok(s("a\nb")));
+        assert!(re_replace(&[s("x"), s("x"), s("y")]).is_err());
+    }
+
+    #[test]
+    fn sets() {
+        let set = Value::set(vec![Value::Int(1), Value::Int(2)]);
+        let grown = conj(&[set.clone(), Value::Int(2), Value::Int(3)]).unwrap();
+        assert_eq!(count("count", &set), Ok(2));
+        assert_eq!(count("count", &grown), Ok(3));
+        assert_eq!(
+            grown,
+            Value::set(vec![Value::Int(3), Value::Int(2), Value::Int(1)])
+        );
+        assert_eq!(members("disj", Value::Nil).map(|s| s.len()), Ok(0));
+        assert!(members("disj", Value::list(vec![])).is_err());
+    }
+
+    #[test]
+    fn growing_sequences() {
+        let list = Value::list(vec![Value::Int(1)]);
+        assert_eq!(
+            conj(&[list, Value::Int(2), Value::Int(3)]).map(|v| v.pr_str(true)),
+            Ok("(3 2 1)".to_owned())
+        );
+        let vector = Value::vector(vec![Value::Int(1)]);
+        assert_eq!(
+            conj(&[vector, Value::Int(2)]).map(|v| v.pr_str(true)),
+            Ok("[1 2]".to_owned())
+        );
+        assert_eq!(
+            seq(&Value::String("ab".to_owned())).map(|v| v.pr_str(true)),
+            Ok("(\"a\" \"b\")".to_owned())
+        );
+        assert_eq!(seq(&Value::vector(vec![])), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn atoms() {
+        let a = Value::atom(Value::Int(2));
+        let add = Value::builtin(Arity::at_least(0), |args| arithmetic(args, 0, numeric::add));
+        assert_eq!(swap(vec![a.clone(), add, Value::Int(3)]), Ok(Value::Int(5)));
+        assert_eq!(a.pr_str(true), "(atom 5)");
+        assert_eq!(
+            swap(vec![Value::Int(1), Value::Nil]),
+            Err(Error::EvalError(
+                "`swap!` expects an atom, found integer 1".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn reading_strings() {
+        assert_eq!(
+            read_string("(1 \"a\") 2", &ReaderConfig::default()),
+            Ok(Value::list(vec![
+                Value::Int(1),
+                Value::String("a".to_owned())
+            ]))
+        );
+        assert_eq!(
+            read_string(";; comment", &ReaderConfig::default()),
+            Ok(Value::Nil)
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::error::Error;
+use std::cell::Cell;
+use std::env;
+use std::io;
+use std::panic;
+use std::thread;
+
+/// How deep evaluation may nest unless configured otherwise. Tail calls do
+/// not count, only forms waiting for the value of a nested form.
+pub(crate) const DEFAULT_LIMIT: usize = 10_000;
+
+/// Environment variable overriding `DEFAULT_LIMIT`.
+const LIMIT_VAR: &str = "MAL_MAX_DEPTH";
+
+/// Native stack reserved per level of nesting, with room to spare for
+/// unoptimised builds.
+const STACK_PER_LEVEL: usize = 32 * 1024;
+
+thread_local! {
+    static DEPTH: Cell<usize> = Cell::new(0);
+    static LIMIT: Cell<usize> = Cell::new(DEFAULT_LIMIT);
+}
+
+pub(crate) fn set_limit(limit: usize) {
+    LIMIT.with(|l| l.set(limit));
+}
+
+/// The limit from `MAL_MAX_DEPTH`, or `DEFAULT_LIMIT` when it is unset or
+/// not a number.
+pub(crate) fn configured_limit() -> usize {
+    env::var(LIMIT_VAR)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+}
+
+/// Runs `f` with `limit` in force, on a thread whose stack is large enough
+/// that hitting the limit is an error rather than a crash. Fails if the
+/// system cannot provide a stack that large.
+pub(crate) fn run_with_limit<T: Send + 'static>(
+    limit: usize,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> io::Result<T> {
+    let stack_size = limit.saturating_add(1).saturating_mul(STACK_PER_LEVEL);
+    let thread = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || {
+            set_limit(limit);
+            f()
+        })?;
+    Ok(thread.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+}
+
+/// One level of nested evaluation, left again when dropped.
+pub(crate) struct DepthGuard(());
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
+
+/// Enters one more level of nesting, failing with `Error::StackOverflow`
+/// instead of letting the native stack run out.
+pub(crate) fn enter() -> Result<DepthGuard, Error> {
+    let limit = LIMIT.with(Cell::get);
+    DEPTH.with(|d| {
+        if d.get() >= limit {
+            Err(Error::StackOverflow(limit))
+        } else {
+            d.set(d.get() + 1);
+            Ok(DepthGuard(()))
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit() {
+        set_limit(2);
+        let first = enter().unwrap();
+        let second = enter().unwrap();
+        assert_eq!(enter().err(), Some(Error::StackOverflow(2)));
+        drop(second);
+        let third = enter();
+        assert!(third.is_ok());
+        drop(third);
+        drop(first);
+        set_limit(DEFAULT_LIMIT);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::error::Error;
+use std::fmt::Write;
+
+/// Renders `err` rustc-style: the message, then the offending line of
+/// `source` with a caret under the reported span.
+///
+/// ```text
+/// error: unbalanced parens
+///  --> 2:3
+///   |
+/// 2 |  2]
+///   |   ^
+/// ```
+///
+/// Errors from a loaded file are shown against that file instead, with its
+/// path before the location.
+pub(crate) fn render(source: &str, err: &Error) -> String {
+    match err {
+        Error::InFile { path, text, error } => render_in(Some(path.as_str()), text, error),
+        _ => render_in(None, source, err),
+    }
+}
+
+fn render_in(path: Option<&str>, source: &str, err: &Error) -> String {
+    let mut out = format!("error: {}", err.kind());
+    let span = match err.span() {
+        Some(span) => span,
+        None => return out,
+    };
+    // A span from some other input, which has nothing to point at here.
+    let line = match source.lines().nth(span.line - 1) {
+        Some(line) if span.column <= line.chars().count() + 1 => line,
+        _ => return out,
+    };
+    let gutter = " ".repeat(span.line.to_string().len());
+    let before: String = line
+        .chars()
+        .take(span.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = source
+        .get(span.start..span.end)
+        .map(|s| s.lines().next().unwrap_or("").chars().count())
+        .unwrap_or(0)
+        .max(1);
+
+    let origin = path.map(|p| format!("{}:", p)).unwrap_or_default();
+    write!(out, "\n{}--> {}{}", gutter, origin, span).unwrap();
+    write!(out, "\n{} |", gutter).unwrap();
+    write!(out, "\n{} | {}", span.line, line).unwrap();
+    write!(out, "\n{} | {}{}", gutter, before, "^".repeat(width)).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Span;
+    use crate::reader;
+
+    #[test]
+    fn caret_under_offending_char() {
+        let source = "(+ 1\n  2]";
+        let err = reader::read_all(source).err().unwrap();
+        assert_eq!(
+            render(source, &err),
+            "error: unbalanced parens\n --> 2:4\n  |\n2 |   2]\n  |    ^"
+        );
+    }
+
+    #[test]
+    fn caret_spans_token() {
+        let source = "(1 1337s)";
+        let err = reader::read_all(source).err().unwrap();
+        assert_eq!(
+            render(source, &err),
+            "error: unexpected `s` in number `1337`\n --> 1:8\n  |\n1 | (1 1337s)\n  |        ^"
+        );
+    }
+
+    #[test]
+    fn loaded_file() {
+        let text = "(def! a 1)\n  (]";
+        let err = reader::read_all(text)
+            .err()
+            .unwrap()
+            .in_file("lib.mal", text);
+        assert_eq!(
+            render("(load-file \"lib.mal\")", &err),
+            "error: unbalanced parens\n --> lib.mal:2:4\n  |\n2 |   (]\n  |    ^"
+        );
+    }
+
+    #[test]
+    fn span_outside_source() {
+        let span = Span {
+            start: 17,
+            end: 29,
+            line: 1,
+            column: 18,
+        };
+        let err = Error::EvalError("expected a number".to_owned()).at(span);
+        assert_eq!(render("(f 1)", &err), "error: expected a number");
+        let span = Span { line: 3, ..span };
+        let err = Error::EvalError("expected a number".to_owned()).at(span);
+        assert_eq!(render("(f 1)", &err), "error: expected a number");
+    }
+
+    #[test]
+    fn no_span() {
+        let err = Error::EvalError("'abc' not found".to_owned());
+        assert_eq!(render("(abc)", &err), "error: 'abc' not found");
+    }
+}
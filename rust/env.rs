@@ -0,0 +1,35 @@
+use crate::error::Error;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A scope of bindings. Lookups that miss continue in the enclosing scope,
+/// so closures keep their defining environment alive through `parent`.
+pub(crate) struct Env {
+    env: HashMap<String, Value>,
+    parent: Option<Rc<RefCell<Env>>>,
+}
+
+impl Env {
+    pub(crate) fn new(parent: Option<Rc<RefCell<Env>>>) -> Rc<RefCell<Env>> {
+        Rc::new(RefCell::new(Env {
+            env: HashMap::new(),
+            parent,
+        }))
+    }
+
+    pub(crate) fn set(&mut self, symbol: String, value: Value) {
+        self.env.insert(symbol, value);
+    }
+
+    pub(crate) fn search(&self, symbol: &str) -> Result<Value, Error> {
+        if let Some(value) = self.env.get(symbol) {
+            Ok(value.clone())
+        } else if let Some(parent) = &self.parent {
+            parent.borrow().search(symbol)
+        } else {
+            Err(Error::EvalError(format!("'{}' not found", symbol)))
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::lexer::Span;
+use crate::reader::Embedded;
+use std::panic::{self, AssertUnwindSafe};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("{0}")]
+    TransitionError(String),
+    #[error("{0}")]
+    TokenTerminationError(String),
+    #[error("unbalanced parens")]
+    Unbalanced,
+    #[error("unexpected EOF while reading a string")]
+    EOF,
+    #[error("unexpected end of input, {0}")]
+    Incomplete(String),
+    #[error("a map needs an even number of forms, found {0}")]
+    OddMap(usize),
+    #[error("invalid regex: {0}")]
+    InvalidRegex(String),
+    /// A reader macro prefix without the form(s) it applies to.
+    #[error("{0}")]
+    ReaderMacroError(String),
+    #[error("{0}")]
+    EvalError(String),
+    #[error("stack overflow: recursion deeper than {0} levels")]
+    StackOverflow(usize),
+    /// A value raised by `throw`, which `catch*` binds as it is.
+    #[error("uncaught exception: {0}")]
+    Thrown(Embedded),
+    #[error("internal error: {0}")]
+    Internal(String),
+    #[error("{1} at {0}")]
+    Located(Span, Box<Error>),
+    /// An error located in a loaded file rather than in the input being
+    /// evaluated; `text` is the file's contents.
+    #[error("{error} in {path}")]
+    InFile {
+        path: String,
+        text: String,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Attaches a source location to the error unless it already has a
+    /// more precise one.
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            Error::Located(..) | Error::InFile { .. } => self,
+            e => Error::Located(span, Box::new(e)),
+        }
+    }
+
+    /// Drops the error's source location, for errors leaving a function's
+    /// body: that body may have been read from another input, so the error
+    /// is located again at the call instead. Errors from a loaded file keep
+    /// theirs.
+    pub fn unlocated(self) -> Self {
+        match self {
+            Error::Located(_, e) => *e,
+            e => e,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Located(span, _) => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Whether the input merely ended too early, so that reading more of it
+    /// could make it valid.
+    pub fn is_incomplete(&self) -> bool {
+        match self.kind() {
+            Error::EOF | Error::Incomplete(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Marks the error as coming from the file at `path` with contents
+    /// `text`, unless it already comes from a file loaded from there.
+    pub fn in_file(self, path: &str, text: &str) -> Self {
+        match self {
+            Error::InFile { .. } => self,
+            e => Error::InFile {
+                path: path.to_owned(),
+                text: text.to_owned(),
+                error: Box::new(e),
+            },
+        }
+    }
+
+    /// The error itself, without location information.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Located(_, e) => e.kind(),
+            Error::InFile { error, .. } => error.kind(),
+            e => e,
+        }
+    }
+}
+
+/// Runs `f`, reporting a panic inside it as `Error::Internal` instead of
+/// unwinding further. This is the last line of defence that keeps a REPL
+/// session alive when the interpreter itself has a bug.
+pub(crate) fn catch_panic<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        Err(Error::Internal(message))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn panics_become_errors() {
+        assert_eq!(catch_panic(|| Ok(1)), Ok(1));
+        let result: Result<(), Error> = catch_panic(|| panic!("boom {}", 1));
+        assert_eq!(result, Err(Error::Internal("boom 1".to_owned())));
+        let result: Result<(), Error> = catch_panic(|| panic!("boom"));
+        assert_eq!(result, Err(Error::Internal("boom".to_owned())));
+    }
+
+    #[test]
+    fn relocation() {
+        let oops = || Error::EvalError("oops".to_owned());
+        assert_eq!(oops().at(Span::default()).unlocated(), oops());
+        assert_eq!(
+            oops().in_file("a.mal", "").unlocated(),
+            oops().in_file("a.mal", "")
+        );
+    }
+
+    #[test]
+    fn thrown_values() {
+        use crate::reader::Embed;
+        use std::any::Any;
+        use std::fmt;
+
+        #[derive(PartialEq)]
+        struct Oops;
+        impl fmt::Display for Oops {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "\"oops\"")
+            }
+        }
+        impl Embed for Oops {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+            fn equals(&self, other: &dyn Embed) -> bool {
+                other.as_any().downcast_ref() == Some(self)
+            }
+        }
+
+        let thrown = Error::Thrown(Embedded::new(Oops));
+        assert_eq!(thrown.to_string(), "uncaught exception: \"oops\"");
+        let located = thrown.at(Span::default()).in_file("a.mal", "");
+        assert_eq!(located.kind(), &Error::Thrown(Embedded::new(Oops)));
+    }
+}
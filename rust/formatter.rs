@@ -1,40 +1,140 @@
 use crate::reader::{Ast, AstLeaf, ListType};
 use std::fmt;
 
+// `{}` prints forms readably, so that reading the output gives back the
+// same form; the alternate flag `{:#}` prints strings raw instead.
+
+pub(crate) fn write_escaped(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Writes a regex as a `#"..."` literal that reads back as the same
+/// pattern: only a `"` needs escaping.
+pub(crate) fn write_regex(f: &mut fmt::Formatter, pattern: &str) -> fmt::Result {
+    write!(f, "#\"{}\"", pattern.replace('"', "\\\""))
+}
+
+/// Writes a float so that it reads back as a float: with a fractional
+/// part even when it is whole, and non-finite values as `##Inf`, `##-Inf`
+/// and `##NaN`.
+pub(crate) fn write_float(f: &mut fmt::Formatter, x: f64) -> fmt::Result {
+    if x.is_nan() {
+        write!(f, "##NaN")
+    } else if x.is_infinite() {
+        write!(f, "##{}Inf", if x < 0.0 { "-" } else { "" })
+    } else if x.fract() == 0.0 {
+        write!(f, "{}.0", x)
+    } else {
+        write!(f, "{}", x)
+    }
+}
+
 impl fmt::Display for AstLeaf {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             AstLeaf::Symbol(x) => write!(f, "{}", x),
-            AstLeaf::String(x) => write!(f, "\"{}\"", x),
+            AstLeaf::String(x) if f.alternate() => write!(f, "{}", x),
+            AstLeaf::String(x) => write_escaped(f, x),
+            AstLeaf::Keyword(x) => write!(f, ":{}", x),
+            AstLeaf::Bool(x) => write!(f, "{}", x),
+            AstLeaf::Nil => write!(f, "nil"),
+            AstLeaf::Regex(p) if f.alternate() => write!(f, "{}", p.as_str()),
+            AstLeaf::Regex(p) => write_regex(f, p.as_str()),
             AstLeaf::Int(x) => write!(f, "{}", x.to_string()),
-            AstLeaf::Float(x) => write!(f, "{}", x.to_string()),
-            AstLeaf::Function(lf) => write!(f, "{:?}", lf),
+            AstLeaf::Float(x) => write_float(f, *x),
+            AstLeaf::Function(_) => write!(f, "#<function>"),
+            AstLeaf::Embedded(e) => e.fmt(f),
+        }
+    }
+}
+
+/// The elements of a list in the order they are written.
+pub(crate) type Elements<'a, T> = Box<dyn Iterator<Item = &'a T> + 'a>;
+
+/// Writes a tree of forms without recursing, so that pathologically deep
+/// nesting cannot exhaust the native stack. `split` gives the delimiters
+/// and elements of a list, or `None` for a leaf, which `leaf` writes.
+pub(crate) fn write_tree<'a, T>(
+    f: &mut fmt::Formatter,
+    root: &'a T,
+    split: impl Fn(&'a T) -> Option<(&'static str, Elements<'a, T>, &'static str)>,
+    leaf: impl Fn(&T, &mut fmt::Formatter) -> fmt::Result,
+) -> fmt::Result {
+    let mut open: Vec<(Elements<'a, T>, &'static str)> = Vec::new();
+    let mut node = root;
+    loop {
+        match split(node) {
+            None => leaf(node, f)?,
+            Some((lp, items, rp)) => {
+                write!(f, "{}", lp)?;
+                let mut items = items;
+                if let Some(first) = items.next() {
+                    open.push((items, rp));
+                    node = first;
+                    continue;
+                }
+                write!(f, "{}", rp)?;
+            }
+        }
+        // `node` is written: close every list it was the last element of.
+        loop {
+            let (items, rp) = match open.last_mut() {
+                Some(top) => top,
+                None => return Ok(()),
+            };
+            if let Some(next) = items.next() {
+                write!(f, " ")?;
+                node = next;
+                break;
+            }
+            write!(f, "{}", rp)?;
+            open.pop();
         }
     }
 }
 
 impl fmt::Display for Ast {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Ast::Leaf(x) => write!(f, "{}", x),
-            Ast::List(xs) => {
-                let (lp, rp) = match xs.list_type {
-                    ListType::Parens => ('(', ')'),
-                    ListType::Braces => ('{', '}'),
-                    ListType::Brackets => ('[', ']'),
-                };
-                let lex = &xs.list;
-                if lex.is_empty() {
-                    write!(f, "{}{}", lp, rp)
-                } else {
-                    let mut iter = lex.iter();
-                    write!(f, "{}{}", lp, iter.next().expect("What could go wrong"))?;
-                    for i in iter {
-                        write!(f, " {}", i)?;
-                    }
-                    write!(f, "{}", rp)
+        write_tree(
+            f,
+            self,
+            |ast| match ast {
+                Ast::Leaf(..) => None,
+                Ast::List(xs) => {
+                    let (lp, rp) = match xs.list_type {
+                        ListType::Parens => ("(", ")"),
+                        ListType::Braces => ("{", "}"),
+                        ListType::Brackets => ("[", "]"),
+                        ListType::Set => ("#{", "}"),
+                    };
+                    Some((lp, Box::new(xs.list.iter()) as Elements<_>, rp))
                 }
-            }
+            },
+            |ast, f| match ast {
+                Ast::Leaf(x, _) => x.fmt(f),
+                Ast::List(_) => Ok(()),
+            },
+        )
+    }
+}
+
+impl Ast {
+    /// Prints the form; `readably` escapes strings the way `prn` does,
+    /// otherwise they are printed raw as by `println`.
+    pub(crate) fn pr_str(&self, readably: bool) -> String {
+        if readably {
+            format!("{}", self)
+        } else {
+            format!("{:#}", self)
         }
     }
 }
@@ -1,12 +1,11 @@
-use super::Error;
+use crate::error::Error;
+use std::cmp::Reverse;
+use std::fmt;
 use std::mem;
 
 macro_rules! trans_err {
     ($c:expr, $buff:expr, $state:expr) => {
-        Err(Error::TransitionError(format!(
-            "Unexpected character: {}, buffer: {}, state: {:?}",
-            $c, $buff, $state
-        )))
+        Err(Error::TransitionError($state.unexpected($c, &$buff)))
     };
 }
 
@@ -40,17 +39,120 @@ enum State {
     Float,
     StringStart,
     Escape,
+    UnicodeStart,
+    Unicode,
     StringClose,
     Symbol,
     Comment,
     Tilda,
+    /// After a `#`, which may start a dispatch form such as `#{`.
+    Dispatch,
+    /// Inside a `#"..."` regex, which keeps every character as written.
+    Regex,
+    /// After a `\` in a regex: `\"` stands for a `"`, any other pair is
+    /// kept as written for the regex to interpret.
+    RegexEscape,
+    RegexClose,
+}
+
+impl State {
+    /// Message for character `c` that cannot continue the token in `buffer`.
+    fn unexpected(&self, c: char, buffer: &str) -> String {
+        match self {
+            State::Num | State::Float => format!("unexpected `{}` in number `{}`", c, buffer),
+            State::Dot => format!("expected a digit after `{}`, found `{}`", buffer, c),
+            State::Minus | State::Symbol => format!("unexpected `{}` in symbol `{}`", c, buffer),
+            State::StringClose => format!("unexpected `{}` right after string \"{}\"", c, buffer),
+            State::RegexClose => format!("unexpected `{}` right after regex #\"{}\"", c, buffer),
+            _ => format!("unexpected `{}`", c),
+        }
+    }
+
+    /// Message for input that ends while the token in `buffer` is unfinished.
+    fn unterminated(&self, buffer: &str) -> String {
+        match self {
+            State::Dot => format!("expected a digit after `{}`", buffer),
+            _ => format!("unfinished token `{}`", buffer),
+        }
+    }
+}
+
+/// Location of a token or form in the source: byte range plus the
+/// 1-based line and column of its first character.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span {
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Span {
+    /// Span covering everything from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn advance(&mut self, c: char) {
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+
+    fn span_to(self, end: usize) -> Span {
+        Span {
+            start: self.offset,
+            end,
+            line: self.line,
+            column: self.column,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Lexer {
     state: State,
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, Span)>,
     buffer: String,
+    escape: String,
+    pos: Position,
+    token_start: Position,
+    /// Reader macro prefixes split off wherever a token may start, longest
+    /// first.
+    prefixes: Vec<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -61,10 +163,36 @@ pub enum Token {
     RightBracket,
     LeftBrace,
     RightBrace,
+    /// `#{`, which opens a set.
+    LeftSetBrace,
+    /// `#(`, which opens an anonymous function.
+    LeftFnParen,
+    /// `#_`, which discards the form after it.
+    Discard,
     String(String),
-    Int(i32),
-    Float(f32),
+    /// The pattern of a `#"..."` literal, exactly as written.
+    Regex(String),
+    Int(i64),
+    Float(f64),
     Symbol(String),
+    Keyword(String),
+    Bool(bool),
+    Nil,
+}
+
+/// Symbols that are really literals: `nil`, booleans, the non-finite
+/// floats `##Inf`, `##-Inf` and `##NaN`, and `:keywords`.
+fn symbol_token(b: String) -> Token {
+    match b.as_str() {
+        "nil" => Token::Nil,
+        "true" => Token::Bool(true),
+        "false" => Token::Bool(false),
+        "##Inf" => Token::Float(f64::INFINITY),
+        "##-Inf" => Token::Float(f64::NEG_INFINITY),
+        "##NaN" => Token::Float(f64::NAN),
+        s if s.starts_with(':') && s.len() > 1 => Token::Keyword(s[1..].to_owned()),
+        _ => Token::Symbol(b),
+    }
 }
 
 fn is_whitespace(c: char) -> bool {
@@ -73,13 +201,39 @@ fn is_whitespace(c: char) -> bool {
 
 impl Lexer {
     pub fn new() -> Self {
+        let origin = Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
         Lexer {
             state: State::Init,
             tokens: vec![],
             buffer: "".to_owned(),
+            escape: "".to_owned(),
+            pos: origin,
+            token_start: origin,
+            prefixes: vec![],
+        }
+    }
+
+    /// A lexer that also reads each of `prefixes` as a symbol of its own
+    /// where a token may start, so that `#'x` can be `#'` followed by `x`.
+    pub fn with_prefixes(mut prefixes: Vec<String>) -> Self {
+        prefixes.sort_by_key(|prefix| Reverse(prefix.len()));
+        Lexer {
+            prefixes,
+            ..Lexer::new()
         }
     }
 
+    fn prefix_at(&self, rest: &str) -> Option<String> {
+        self.prefixes
+            .iter()
+            .find(|prefix| rest.starts_with(prefix.as_str()))
+            .cloned()
+    }
+
     fn trans_ignore(&mut self, state: State) {
         self.state = state;
     }
@@ -94,21 +248,31 @@ impl Lexer {
     }
 
     fn end_token_trans(&mut self, new_state: State) -> Result<(), Error> {
-        static EXPECT_NUM: &'static str = "Programming error in lexer: Parse num";
+        let span = self.token_start.span_to(self.pos.offset);
         let b = mem::replace(&mut self.buffer, "".to_owned());
+        let out_of_range =
+            |b: &str| Error::TokenTerminationError(format!("number `{}` is out of range", b));
         let token = match &self.state {
             State::Minus => Ok(Token::Symbol(b)),
-            State::Num => Ok(Token::Int(b.parse().expect(EXPECT_NUM))),
-            State::Float => Ok(Token::Float(b.parse().expect(EXPECT_NUM))),
+            State::Num => b.parse().map(Token::Int).map_err(|_| out_of_range(&b)),
+            State::Float => match b.parse::<f64>() {
+                Ok(f) if f.is_finite() => Ok(Token::Float(f)),
+                _ => Err(out_of_range(&b)),
+            },
             State::StringClose => Ok(Token::String(b)),
-            State::Symbol => Ok(Token::Symbol(b)),
-            State::Tilda => Ok(Token::Symbol(b)),
-            _ => Err(Error::TokenTerminationError(format!(
-                "Non terminating state: {:?}",
-                self.state
-            ))),
-        }?;
-        self.push_token(token);
+            State::RegexClose => Ok(Token::Regex(b)),
+            State::Symbol => Ok(symbol_token(b)),
+            State::Tilda | State::Dispatch => Ok(Token::Symbol(b)),
+            State::StringStart | State::Escape | State::UnicodeStart | State::Unicode => {
+                Err(Error::EOF)
+            }
+            State::Regex | State::RegexEscape => Err(Error::Incomplete(
+                "expected `\"` to close the regex".to_owned(),
+            )),
+            _ => Err(Error::TokenTerminationError(self.state.unterminated(&b))),
+        }
+        .map_err(|e| e.at(span))?;
+        self.tokens.push((token, span));
         self.state = new_state;
         Ok(())
     }
@@ -118,7 +282,7 @@ impl Lexer {
     }
 
     fn try_end_token(&mut self) -> Result<(), Error> {
-        if !self.buffer.is_empty() {
+        if self.state != State::Init && self.state != State::Comment {
             self.end_token()
         } else {
             Ok(())
@@ -131,8 +295,10 @@ impl Lexer {
         Ok(())
     }
 
+    /// Pushes a token made of the character currently being processed.
     fn push_token(&mut self, t: Token) {
-        self.tokens.push(t);
+        let span = self.pos.span_to(self.pos.offset + 1);
+        self.tokens.push((t, span));
     }
 
     fn trans_init(&mut self, c: char) -> Result<(), Error> {
@@ -143,6 +309,7 @@ impl Lexer {
             '`' => Ok(self.push_token(Token::Symbol("`".to_owned()))),
             '@' => Ok(self.push_token(Token::Symbol("@".to_owned()))),
             '~' => Ok(self.trans(c, State::Tilda)),
+            '#' => Ok(self.trans(c, State::Dispatch)),
             '(' => Ok(self.push_token(Token::LeftParen)),
             ')' => Ok(self.push_token(Token::RightParen)),
             '[' => Ok(self.push_token(Token::LeftBracket)),
@@ -182,7 +349,7 @@ impl Lexer {
     fn trans_string_start(&mut self, c: char) -> Result<(), Error> {
         match c {
             '"' => Ok(self.trans_ignore(State::StringClose)),
-            '\\' => Ok(self.trans(c, State::Escape)),
+            '\\' => Ok(self.trans_ignore(State::Escape)),
             c if c != '"' => Ok(self.push_buffer(c)),
             _ => trans_err!(c, self.buffer, State::StringStart),
         }
@@ -190,7 +357,41 @@ impl Lexer {
 
     fn trans_escape(&mut self, c: char) -> Result<(), Error> {
         match c {
-            _ => Ok(self.trans(c, State::StringStart)),
+            'n' => Ok(self.trans('\n', State::StringStart)),
+            't' => Ok(self.trans('\t', State::StringStart)),
+            '\\' | '"' => Ok(self.trans(c, State::StringStart)),
+            'u' => Ok(self.trans_ignore(State::UnicodeStart)),
+            _ => Err(Error::TransitionError(format!("unknown escape `\\{}`", c))),
+        }
+    }
+
+    fn trans_unicode_start(&mut self, c: char) -> Result<(), Error> {
+        match c {
+            '{' => Ok(self.trans_ignore(State::Unicode)),
+            _ => Err(Error::TransitionError(format!(
+                "expected `{{` after `\\u`, found `{}`",
+                c
+            ))),
+        }
+    }
+
+    fn trans_unicode(&mut self, c: char) -> Result<(), Error> {
+        match c {
+            c if c.is_ascii_hexdigit() && self.escape.len() < 6 => Ok(self.escape.push(c)),
+            '}' => {
+                let code = mem::replace(&mut self.escape, "".to_owned());
+                let decoded = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| {
+                        Error::TransitionError(format!("invalid unicode escape `\\u{{{}}}`", code))
+                    })?;
+                Ok(self.trans(decoded, State::StringStart))
+            }
+            _ => Err(Error::TransitionError(format!(
+                "unexpected `{}` in unicode escape `\\u{{{}`",
+                c, self.escape
+            ))),
         }
     }
 
@@ -200,6 +401,27 @@ impl Lexer {
         }
     }
 
+    fn trans_regex(&mut self, c: char) -> Result<(), Error> {
+        match c {
+            '"' => Ok(self.trans_ignore(State::RegexClose)),
+            '\\' => Ok(self.trans(c, State::RegexEscape)),
+            _ => Ok(self.push_buffer(c)),
+        }
+    }
+
+    fn trans_regex_escape(&mut self, c: char) {
+        if c == '"' {
+            self.buffer.pop();
+        }
+        self.trans(c, State::Regex);
+    }
+
+    fn trans_regex_close(&mut self, c: char) -> Result<(), Error> {
+        match_terminated! {self, c,
+            _ => trans_err!(c, self.buffer, State::RegexClose)
+        }
+    }
+
     fn trans_minus(&mut self, c: char) -> Result<(), Error> {
         match_terminated! {self, c,
             c if c.is_digit(10) => Ok(self.trans(c, State::Num)),
@@ -237,8 +459,11 @@ impl Lexer {
     fn trans_tilda(&mut self, c: char) -> Result<(), Error> {
         match c {
             '@' => {
-                self.push_buffer(c);
-                self.end_token()
+                self.buffer.clear();
+                let span = self.token_start.span_to(self.pos.offset + 1);
+                self.tokens.push((Token::Symbol("~@".to_owned()), span));
+                self.state = State::Init;
+                Ok(())
             }
             _ => {
                 self.end_token()?;
@@ -247,7 +472,33 @@ impl Lexer {
         }
     }
 
+    /// After `#`: `#{`, `#(` and `#_` are tokens of their own, `#"` starts
+    /// a regex, anything else continues a symbol starting with `#`.
+    fn trans_dispatch(&mut self, c: char) -> Result<(), Error> {
+        let token = match c {
+            '"' => {
+                self.buffer.clear();
+                return Ok(self.trans_ignore(State::Regex));
+            }
+            '{' => Token::LeftSetBrace,
+            '(' => Token::LeftFnParen,
+            '_' => Token::Discard,
+            _ => {
+                self.state = State::Symbol;
+                return self.trans_symbol(c);
+            }
+        };
+        self.buffer.clear();
+        let span = self.token_start.span_to(self.pos.offset + 1);
+        self.tokens.push((token, span));
+        self.state = State::Init;
+        Ok(())
+    }
+
     fn process_char(&mut self, c: char) -> Result<(), Error> {
+        if self.state == State::Init {
+            self.token_start = self.pos;
+        }
         match self.state {
             State::Init => self.trans_init(c),
             State::Comment => self.trans_comment(c),
@@ -257,19 +508,51 @@ impl Lexer {
             State::Float => self.trans_float(c),
             State::StringStart => self.trans_string_start(c),
             State::Escape => self.trans_escape(c),
+            State::UnicodeStart => self.trans_unicode_start(c),
+            State::Unicode => self.trans_unicode(c),
             State::StringClose => self.trans_string_close(c),
             State::Symbol => self.trans_symbol(c),
             State::Tilda => self.trans_tilda(c),
+            State::Dispatch => self.trans_dispatch(c),
+            State::Regex => self.trans_regex(c),
+            State::RegexEscape => Ok(self.trans_regex_escape(c)),
+            State::RegexClose => self.trans_regex_close(c),
         }
     }
 
-    pub fn tokenize(mut self, input: &str) -> Result<Vec<Token>, Error> {
-        for c in input.chars() {
-            self.process_char(c)?;
+    /// Splits `input` into tokens, each paired with its location in `input`.
+    pub fn tokenize_spanned(mut self, input: &str) -> Result<Vec<(Token, Span)>, Error> {
+        let mut resume = 0;
+        for (offset, c) in input.char_indices() {
+            let pos = self.pos;
+            if offset < resume {
+                self.pos.advance(c);
+                continue;
+            }
+            if self.state == State::Init {
+                if let Some(prefix) = self.prefix_at(&input[offset..]) {
+                    resume = offset + prefix.len();
+                    self.tokens
+                        .push((Token::Symbol(prefix), pos.span_to(resume)));
+                    self.pos.advance(c);
+                    continue;
+                }
+            }
+            self.process_char(c)
+                .map_err(|e| e.at(pos.span_to(pos.offset + c.len_utf8())))?;
+            self.pos.advance(c);
         }
         self.try_end_token()?;
         Ok(self.tokens)
     }
+
+    pub fn tokenize(self, input: &str) -> Result<Vec<Token>, Error> {
+        Ok(self
+            .tokenize_spanned(input)?
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -289,7 +572,12 @@ mod test {
         let err = t_bad.tokenize("1337s").err().unwrap();
         assert_eq!(
             err,
-            Error::TransitionError("Unexpected character: s, buffer: 1337, state: Num".to_owned())
+            Error::TransitionError("unexpected `s` in number `1337`".to_owned()).at(Span {
+                start: 4,
+                end: 5,
+                line: 1,
+                column: 5,
+            })
         );
     }
 
@@ -310,28 +598,75 @@ mod test {
         let err = t_bad.tokenize("1337.").err().unwrap();
         assert_eq!(
             err,
-            Error::TokenTerminationError("Non terminating state: Dot".to_owned())
+            Error::TokenTerminationError("expected a digit after `1337.`".to_owned()).at(Span {
+                start: 0,
+                end: 5,
+                line: 1,
+                column: 1,
+            })
         );
 
         let t_bad2 = Lexer::new();
         let err = t_bad2.tokenize("1337.a").err().unwrap();
         assert_eq!(
             err,
-            Error::TransitionError("Unexpected character: a, buffer: 1337., state: Dot".to_owned())
+            Error::TransitionError("expected a digit after `1337.`, found `a`".to_owned()).at(
+                Span {
+                    start: 5,
+                    end: 6,
+                    line: 1,
+                    column: 6,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn tokenize_wide_numbers() {
+        let t = Lexer::new();
+        let tokens = t
+            .tokenize("9223372036854775807 -9223372036854775808 2.02")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Int(i64::max_value()),
+                Token::Int(i64::min_value()),
+                Token::Float(2.02),
+            ]
+        );
+
+        let err = Lexer::new()
+            .tokenize("(9223372036854775808)")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            Error::TokenTerminationError("number `9223372036854775808` is out of range".to_owned())
+                .at(Span {
+                    start: 1,
+                    end: 20,
+                    line: 1,
+                    column: 2,
+                })
         );
+        let huge = format!("1{}.0", "0".repeat(400));
+        let err = Lexer::new().tokenize(&huge).err().unwrap();
+        assert!(err.to_string().contains("is out of range"));
     }
 
     #[test]
     fn tokenize_symbol() {
         let t = Lexer::new();
-        let tokens = t.tokenize("true false - ----").unwrap();
+        let tokens = t.tokenize("truey - ---- nil? :").unwrap();
         assert_eq!(
             tokens,
             vec![
-                Token::Symbol("true".to_owned()),
-                Token::Symbol("false".to_owned()),
+                Token::Symbol("truey".to_owned()),
                 Token::Symbol("-".to_owned()),
                 Token::Symbol("----".to_owned()),
+                Token::Symbol("nil?".to_owned()),
+                Token::Symbol(":".to_owned()),
             ]
         );
 
@@ -339,10 +674,40 @@ mod test {
         let err = t_bad.tokenize("lol\"kek").err().unwrap();
         assert_eq!(
             err,
-            Error::TransitionError(
-                "Unexpected character: \", buffer: lol, state: Symbol".to_owned()
-            )
+            Error::TransitionError("unexpected `\"` in symbol `lol`".to_owned()).at(Span {
+                start: 3,
+                end: 4,
+                line: 1,
+                column: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn tokenize_literals() {
+        let t = Lexer::new();
+        let tokens = t.tokenize("(nil true false :kw :a-b)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftParen,
+                Token::Nil,
+                Token::Bool(true),
+                Token::Bool(false),
+                Token::Keyword("kw".to_owned()),
+                Token::Keyword("a-b".to_owned()),
+                Token::RightParen,
+            ]
+        );
+        let tokens = Lexer::new()
+            .tokenize("##Inf ##-Inf ##NaN ##Infinity")
+            .unwrap();
+        assert_eq!(
+            tokens[..2],
+            [Token::Float(f64::INFINITY), Token::Float(f64::NEG_INFINITY)]
         );
+        assert!(matches!(tokens[2], Token::Float(f) if f.is_nan()));
+        assert_eq!(tokens[3], Token::Symbol("##Infinity".to_owned()));
     }
 
     #[test]
@@ -356,7 +721,7 @@ mod test {
             vec![
                 Token::String("".to_owned()),
                 Token::String(" ".to_owned()),
-                Token::String("yolo swag()() hihihe \\\" win".to_owned()),
+                Token::String("yolo swag()() hihihe \" win".to_owned()),
                 Token::String("nailed it".to_owned())
             ]
         );
@@ -365,12 +730,47 @@ mod test {
         let err = t_bad.tokenize("\"lol\"kek\"").err().unwrap();
         assert_eq!(
             err,
-            Error::TransitionError(
-                "Unexpected character: k, buffer: lol, state: StringClose".to_owned()
+            Error::TransitionError("unexpected `k` right after string \"lol\"".to_owned()).at(
+                Span {
+                    start: 5,
+                    end: 6,
+                    line: 1,
+                    column: 6,
+                }
             )
         );
     }
 
+    #[test]
+    fn string_escapes() {
+        let t = Lexer::new();
+        let tokens = t
+            .tokenize(r#""a\"b" "tab\there\nnew line" "\\" "\u{48}\u{1F600}!""#)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::String("a\"b".to_owned()),
+                Token::String("tab\there\nnew line".to_owned()),
+                Token::String("\\".to_owned()),
+                Token::String("H\u{1F600}!".to_owned()),
+            ]
+        );
+
+        let err = Lexer::new().tokenize(r#""\q""#).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            &Error::TransitionError("unknown escape `\\q`".to_owned())
+        );
+        let err = Lexer::new().tokenize(r#""\u{D800}""#).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            &Error::TransitionError("invalid unicode escape `\\u{D800}`".to_owned())
+        );
+        let err = Lexer::new().tokenize(r#""abc\"#).err().unwrap();
+        assert_eq!(err.kind(), &Error::EOF);
+    }
+
     #[test]
     fn tokenize_sexp() {
         let t = Lexer::new();
@@ -464,4 +864,75 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn dispatch() {
+        let t = Lexer::new();
+        let tokens = t.tokenize("#{1} #(f %) #_x #tag # a#b #").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftSetBrace,
+                Token::Int(1),
+                Token::RightBrace,
+                Token::LeftFnParen,
+                Token::Symbol("f".to_owned()),
+                Token::Symbol("%".to_owned()),
+                Token::RightParen,
+                Token::Discard,
+                Token::Symbol("x".to_owned()),
+                Token::Symbol("#tag".to_owned()),
+                Token::Symbol("#".to_owned()),
+                Token::Symbol("a#b".to_owned()),
+                Token::Symbol("#".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn regex_literals() {
+        let tokens = Lexer::new().tokenize(r#"(#"\d+\n" #"a\"b" #"")"#).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftParen,
+                Token::Regex(r"\d+\n".to_owned()),
+                Token::Regex(r#"a"b"#.to_owned()),
+                Token::Regex("".to_owned()),
+                Token::RightParen,
+            ]
+        );
+        let err = Lexer::new().tokenize(r#"#"a\""#).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            &Error::Incomplete("expected `\"` to close the regex".to_owned())
+        );
+        let err = Lexer::new().tokenize(r#"#"x"y"#).err().unwrap();
+        assert_eq!(
+            err.kind().to_string(),
+            "unexpected `y` right after regex #\"x\""
+        );
+    }
+
+    #[test]
+    fn token_spans() {
+        let t = Lexer::new();
+        let tokens = t.tokenize_spanned("(def! x\n  \"é\" ~@y)").unwrap();
+        let spans: Vec<(usize, usize, usize, usize)> = tokens
+            .iter()
+            .map(|(_, s)| (s.start, s.end, s.line, s.column))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 1, 1, 1),
+                (1, 5, 1, 2),
+                (6, 7, 1, 7),
+                (10, 14, 2, 3),
+                (15, 17, 2, 7),
+                (17, 18, 2, 9),
+                (18, 19, 2, 10),
+            ]
+        );
+    }
 }
@@ -0,0 +1,267 @@
+use crate::error::Error;
+use crate::reader::{Ast, AstLeaf, ListType};
+use std::cmp::Ordering;
+
+/// Numbers as seen by arithmetic. Operations on two integers stay integral
+/// and fail on overflow; as soon as one side is a float both are promoted.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+/// Short human name of the kind of value `ast` is, for type errors.
+pub(crate) fn type_name(ast: &Ast) -> &'static str {
+    match ast {
+        Ast::Leaf(leaf, _) => match leaf {
+            AstLeaf::Symbol(_) => "symbol",
+            AstLeaf::Int(_) => "integer",
+            AstLeaf::Float(_) => "float",
+            AstLeaf::String(_) => "string",
+            AstLeaf::Keyword(_) => "keyword",
+            AstLeaf::Bool(_) => "boolean",
+            AstLeaf::Nil => "nil",
+            AstLeaf::Regex(_) => "regex",
+            AstLeaf::Function(_) => "function",
+            AstLeaf::Embedded(_) => "value",
+        },
+        Ast::List(l) => match l.list_type {
+            ListType::Parens => "list",
+            ListType::Brackets => "vector",
+            ListType::Braces => "map",
+            ListType::Set => "set",
+        },
+    }
+}
+
+impl Number {
+    pub(crate) fn from_ast(ast: &Ast) -> Result<Self, Error> {
+        match ast {
+            Ast::Leaf(AstLeaf::Int(i), _) => Ok(Number::Int(*i)),
+            Ast::Leaf(AstLeaf::Float(f), _) => Ok(Number::Float(*f)),
+            _ => Err(Error::EvalError(format!(
+                "expected a number, found {} {}",
+                type_name(ast),
+                ast
+            ))
+            .at(ast.span())),
+        }
+    }
+
+    pub(crate) fn to_ast(self) -> Ast {
+        match self {
+            Number::Int(i) => Ast::int(i),
+            Number::Float(f) => Ast::float(f),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+fn binary(
+    a: Number,
+    b: Number,
+    name: &str,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<Number, Error> {
+    match (a, b) {
+        (Number::Int(x), Number::Int(y)) => int_op(x, y)
+            .map(Number::Int)
+            .ok_or_else(|| Error::EvalError(format!("integer overflow in `{}`", name))),
+        (x, y) => Ok(Number::Float(float_op(x.as_f64(), y.as_f64()))),
+    }
+}
+
+/// Integer division by zero is an error; float division follows IEEE 754.
+fn check_divisor(a: Number, b: Number, name: &str) -> Result<(), Error> {
+    match (a, b) {
+        (Number::Int(_), Number::Int(0)) => {
+            Err(Error::EvalError(format!("division by zero in `{}`", name)))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn add(a: Number, b: Number) -> Result<Number, Error> {
+    binary(a, b, "+", i64::checked_add, |x, y| x + y)
+}
+
+pub(crate) fn sub(a: Number, b: Number) -> Result<Number, Error> {
+    binary(a, b, "-", i64::checked_sub, |x, y| x - y)
+}
+
+pub(crate) fn mul(a: Number, b: Number) -> Result<Number, Error> {
+    binary(a, b, "*", i64::checked_mul, |x, y| x * y)
+}
+
+/// Integer division truncates towards zero, like `quot` in Clojure.
+pub(crate) fn div(a: Number, b: Number) -> Result<Number, Error> {
+    check_divisor(a, b, "/")?;
+    binary(a, b, "/", i64::checked_div, |x, y| x / y)
+}
+
+/// Remainder with the sign of the dividend.
+pub(crate) fn rem(a: Number, b: Number) -> Result<Number, Error> {
+    check_divisor(a, b, "rem")?;
+    binary(a, b, "rem", i64::checked_rem, |x, y| x % y)
+}
+
+/// Floored modulo: the result has the sign of the divisor.
+pub(crate) fn modulo(a: Number, b: Number) -> Result<Number, Error> {
+    check_divisor(a, b, "mod")?;
+    binary(
+        a,
+        b,
+        "mod",
+        |x, y| {
+            let r = x.checked_rem(y)?;
+            Some(if r != 0 && (r < 0) != (y < 0) {
+                r + y
+            } else {
+                r
+            })
+        },
+        |x, y| {
+            let r = x % y;
+            if r != 0.0 && (r < 0.0) != (y < 0.0) {
+                r + y
+            } else {
+                r
+            }
+        },
+    )
+}
+
+pub(crate) fn compare(a: Number, b: Number) -> Result<Ordering, Error> {
+    match (a, b) {
+        (Number::Int(x), Number::Int(y)) => Ok(x.cmp(&y)),
+        (x, y) => x
+            .as_f64()
+            .partial_cmp(&y.as_f64())
+            .ok_or_else(|| Error::EvalError("cannot compare NaN".to_owned())),
+    }
+}
+
+/// Folds `op` over `args` left to right, Clojure-style: no arguments give
+/// `identity` and a single `x` gives `op(identity, x)`, so that `(- 5)` is
+/// `-5` and `(/ 2.0)` is `0.5`. Integer division truncates, so `(/ 2)` is
+/// `0`.
+pub(crate) fn fold(
+    identity: Number,
+    args: &[Number],
+    op: fn(Number, Number) -> Result<Number, Error>,
+) -> Result<Number, Error> {
+    match args {
+        [] => Ok(identity),
+        [x] => op(identity, *x),
+        [first, rest @ ..] => rest.iter().try_fold(*first, |acc, x| op(acc, *x)),
+    }
+}
+
+/// Whether every pair of neighbours in `args` satisfies `test`, as in
+/// `(< 1 2 3)`.
+pub(crate) fn compare_chain(args: &[Number], test: fn(Ordering) -> bool) -> Result<bool, Error> {
+    for pair in args.windows(2) {
+        if !test(compare(pair[0], pair[1])?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Number::*;
+
+    #[test]
+    fn promotion() {
+        assert_eq!(add(Int(1), Int(2)), Ok(Int(3)));
+        assert_eq!(add(Int(1), Float(0.5)), Ok(Float(1.5)));
+        assert_eq!(mul(Float(2.0), Int(3)), Ok(Float(6.0)));
+        assert_eq!(sub(Int(1), Int(3)), Ok(Int(-2)));
+    }
+
+    #[test]
+    fn integer_division() {
+        assert_eq!(div(Int(7), Int(2)), Ok(Int(3)));
+        assert_eq!(div(Int(-7), Int(2)), Ok(Int(-3)));
+        assert_eq!(div(Int(7), Float(2.0)), Ok(Float(3.5)));
+        assert_eq!(rem(Int(-7), Int(2)), Ok(Int(-1)));
+        assert_eq!(modulo(Int(-7), Int(2)), Ok(Int(1)));
+        assert_eq!(modulo(Int(7), Int(-2)), Ok(Int(-1)));
+        assert_eq!(modulo(Float(-7.5), Int(2)), Ok(Float(0.5)));
+        assert_eq!(
+            div(Int(1), Int(0)),
+            Err(Error::EvalError("division by zero in `/`".to_owned()))
+        );
+        assert_eq!(div(Float(1.0), Int(0)), Ok(Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(
+            add(Int(i64::max_value()), Int(1)),
+            Err(Error::EvalError("integer overflow in `+`".to_owned()))
+        );
+        assert_eq!(
+            div(Int(i64::min_value()), Int(-1)),
+            Err(Error::EvalError("integer overflow in `/`".to_owned()))
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(compare(Int(1), Int(2)), Ok(Ordering::Less));
+        assert_eq!(compare(Int(2), Float(2.0)), Ok(Ordering::Equal));
+        assert_eq!(compare(Float(2.5), Int(2)), Ok(Ordering::Greater));
+        assert!(compare(Float(std::f64::NAN), Int(2)).is_err());
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold(Int(0), &[], add), Ok(Int(0)));
+        assert_eq!(fold(Int(0), &[Int(1), Int(2), Int(3)], add), Ok(Int(6)));
+        assert_eq!(fold(Int(0), &[Int(5)], sub), Ok(Int(-5)));
+        assert_eq!(
+            fold(Int(0), &[Int(10), Int(1), Float(2.5)], sub),
+            Ok(Float(6.5))
+        );
+        assert_eq!(fold(Int(1), &[Int(100), Int(5), Int(2)], div), Ok(Int(10)));
+        assert_eq!(fold(Int(1), &[Float(4.0)], div), Ok(Float(0.25)));
+        assert_eq!(fold(Int(1), &[Int(2)], div), Ok(Int(0)));
+        assert_eq!(
+            compare_chain(&[Int(1), Int(2), Int(3)], |o| o == Ordering::Less),
+            Ok(true)
+        );
+        assert_eq!(
+            compare_chain(&[Int(1), Int(3), Int(2)], |o| o == Ordering::Less),
+            Ok(false)
+        );
+        assert_eq!(compare_chain(&[Int(1)], |o| o == Ordering::Less), Ok(true));
+    }
+
+    #[test]
+    fn type_errors() {
+        let err = Number::from_ast(&Ast::string("x".to_owned()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            &Error::EvalError("expected a number, found string \"x\"".to_owned())
+        );
+        let err = Number::from_ast(&Ast::parens(vec![Ast::int(1)]))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            &Error::EvalError("expected a number, found list (1)".to_owned())
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use super::Error;
+use crate::error::Error;
 use crate::lexer::Lexer;
+use crate::lexer::Span;
 use crate::lexer::Token;
-use crate::reader_macros;
+use crate::reader_macros::{self, Forms, ReaderConfig};
+use regex::Regex;
+use std::any::Any;
 use std::fmt;
 use std::mem;
 use std::rc::Rc;
@@ -9,14 +12,97 @@ use std::rc::Rc;
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum AstLeaf {
     Symbol(String),
-    Int(i32),
-    Float(f32),
+    Int(i64),
+    Float(f64),
     String(String),
+    Keyword(String),
+    Bool(bool),
+    Nil,
+    Regex(Pattern),
     Function(LFunction),
+    Embedded(Embedded),
+}
+
+/// A compiled `#"..."` regex. Two patterns are equal when they are
+/// written the same, and hash by that source alone. A compiled `Regex` has
+/// interior caches, which clippy's `mutable_key_type` takes for a key that
+/// can change; since they never affect hashing or equality, values holding
+/// a pattern are sound map keys.
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    pub(crate) regex: Rc<Regex>,
+    /// The same pattern anchored at both ends, for `re-matches`.
+    pub(crate) whole: Rc<Regex>,
+}
+
+impl Pattern {
+    pub(crate) fn new(source: &str) -> Result<Self, Error> {
+        let compile =
+            |source: &str| Regex::new(source).map_err(|e| Error::InvalidRegex(e.to_string()));
+        Ok(Pattern {
+            regex: Rc::new(compile(source)?),
+            whole: Rc::new(compile(&format!("^(?:{})$", source))?),
+        })
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// How many arguments a function accepts.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) struct Arity {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Arity {
+    pub(crate) fn exactly(n: usize) -> Self {
+        Arity {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub(crate) fn at_least(n: usize) -> Self {
+        Arity { min: n, max: None }
+    }
+
+    pub(crate) fn between(min: usize, max: usize) -> Self {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    /// Checks that `name` can be called with `given` arguments.
+    pub(crate) fn check(&self, name: &str, given: usize) -> Result<(), Error> {
+        if given >= self.min && self.max.map_or(true, |max| given <= max) {
+            return Ok(());
+        }
+        let count = |n: usize| format!("{} argument{}", n, if n == 1 { "" } else { "s" });
+        let expected = match self.max {
+            Some(max) if max == self.min => count(max),
+            Some(max) if given > max => format!("at most {}", count(max)),
+            _ => format!("at least {}", count(self.min)),
+        };
+        Err(Error::EvalError(format!(
+            "`{}` expects {}, got {}",
+            name, expected, given
+        )))
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct LFunction {
+    pub(crate) arity: Arity,
     pub(crate) f: Rc<dyn Fn(Vec<Ast>) -> Result<Ast, Error>>,
 }
 
@@ -29,14 +115,59 @@ impl PartialEq for LFunction {
 impl fmt::Debug for LFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("LFunction")
+            .field("arity", &self.arity)
             .field("f", &Rc::as_ptr(&self.f))
             .finish()
     }
 }
 
 impl LFunction {
-    pub(crate) fn new(f: impl Fn(Vec<Ast>) -> Result<Ast, Error> + 'static) -> Self {
-        LFunction { f: Rc::new(f) }
+    pub(crate) fn new(arity: Arity, f: impl Fn(Vec<Ast>) -> Result<Ast, Error> + 'static) -> Self {
+        LFunction {
+            arity,
+            f: Rc::new(f),
+        }
+    }
+}
+
+/// A run-time value of a type only the evaluator knows, such as a function
+/// handed to `eval` or a value raised by `throw`.
+pub(crate) trait Embed: fmt::Display {
+    fn as_any(&self) -> &dyn Any;
+    /// Whether `other` is an equal value of the same type.
+    fn equals(&self, other: &dyn Embed) -> bool;
+}
+
+/// A run-time value carried where the reader's types cannot name it: in
+/// code, standing for itself, or in an `Error::Thrown`. The reader never
+/// produces one; whoever made it gets it back with `downcast_ref`.
+#[derive(Clone)]
+pub(crate) struct Embedded(Rc<dyn Embed>);
+
+impl Embedded {
+    pub(crate) fn new(value: impl Embed + 'static) -> Self {
+        Embedded(Rc::new(value))
+    }
+    pub(crate) fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.as_any().downcast_ref()
+    }
+}
+
+impl PartialEq for Embedded {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.equals(&*other.0)
+    }
+}
+
+impl fmt::Debug for Embedded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Embedded({})", self.0)
+    }
+}
+
+impl fmt::Display for Embedded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
     }
 }
 
@@ -58,71 +189,132 @@ pub(crate) enum ListType {
     Parens,
     Brackets,
     Braces,
+    /// `#{...}`, a set literal.
+    Set,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub(crate) struct AstList {
     pub(crate) list_type: ListType,
     pub(crate) list: Vec<Ast>,
+    pub(crate) span: Span,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub(crate) enum Ast {
-    List(AstList),
-    Leaf(AstLeaf),
+    List(Rc<AstList>),
+    Leaf(AstLeaf, Span),
+}
+
+// Spans are not part of a form's identity: `(+ 1 2)` read from two
+// different places in a file is the same form.
+impl PartialEq for AstList {
+    fn eq(&self, other: &Self) -> bool {
+        self.list_type == other.list_type && self.list == other.list
+    }
+}
+
+// Dropping a deeply nested form would otherwise recurse once per level.
+impl Drop for AstList {
+    fn drop(&mut self) {
+        let mut forms = mem::take(&mut self.list);
+        while let Some(form) = forms.pop() {
+            if let Ast::List(list) = form {
+                if let Ok(mut list) = Rc::try_unwrap(list) {
+                    forms.append(&mut list.list);
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Ast::List(a), Ast::List(b)) => a == b,
+            (Ast::Leaf(a, _), Ast::Leaf(b, _)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 impl Default for Ast {
     fn default() -> Self {
-        Ast::Leaf(AstLeaf::Symbol("".to_owned()))
+        Ast::symbol("".to_owned())
     }
 }
 
 impl Ast {
-    pub(crate) fn function(f: impl Fn(Vec<Ast>) -> Result<Ast, Error> + 'static) -> Self {
-        Ast::Leaf(AstLeaf::Function(LFunction::new(f)))
+    pub(crate) fn leaf(leaf: AstLeaf) -> Self {
+        Ast::Leaf(leaf, Span::default())
+    }
+    pub(crate) fn function(
+        arity: Arity,
+        f: impl Fn(Vec<Ast>) -> Result<Ast, Error> + 'static,
+    ) -> Self {
+        Ast::leaf(AstLeaf::Function(LFunction::new(arity, f)))
     }
     pub(crate) fn symbol(s: String) -> Self {
-        Ast::Leaf(AstLeaf::Symbol(s))
+        Ast::leaf(AstLeaf::Symbol(s))
     }
-    pub(crate) fn int(i: i32) -> Self {
-        Ast::Leaf(AstLeaf::Int(i))
+    pub(crate) fn int(i: i64) -> Self {
+        Ast::leaf(AstLeaf::Int(i))
     }
-    pub(crate) fn float(f: f32) -> Self {
-        Ast::Leaf(AstLeaf::Float(f))
+    pub(crate) fn float(f: f64) -> Self {
+        Ast::leaf(AstLeaf::Float(f))
     }
     pub(crate) fn string(s: String) -> Self {
-        Ast::Leaf(AstLeaf::String(s))
+        Ast::leaf(AstLeaf::String(s))
     }
-    pub(crate) fn parens(sib: Vec<Self>) -> Self {
-        Ast::List(AstList {
-            list_type: ListType::Parens,
+    pub(crate) fn keyword(s: String) -> Self {
+        Ast::leaf(AstLeaf::Keyword(s))
+    }
+    pub(crate) fn bool(b: bool) -> Self {
+        Ast::leaf(AstLeaf::Bool(b))
+    }
+    pub(crate) fn nil() -> Self {
+        Ast::leaf(AstLeaf::Nil)
+    }
+    pub(crate) fn list(list_type: ListType, sib: Vec<Self>) -> Self {
+        Ast::List(Rc::new(AstList {
+            list_type,
             list: sib,
-        })
+            span: Span::default(),
+        }))
+    }
+    pub(crate) fn parens(sib: Vec<Self>) -> Self {
+        Ast::list(ListType::Parens, sib)
     }
     pub(crate) fn braces(sib: Vec<Self>) -> Self {
-        Ast::List(AstList {
-            list_type: ListType::Braces,
-            list: sib,
-        })
+        Ast::list(ListType::Braces, sib)
     }
     pub(crate) fn brackets(sib: Vec<Self>) -> Self {
-        Ast::List(AstList {
-            list_type: ListType::Brackets,
-            list: sib,
-        })
+        Ast::list(ListType::Brackets, sib)
+    }
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            Ast::List(l) => l.span,
+            Ast::Leaf(_, span) => *span,
+        }
+    }
+    pub(crate) fn with_span(mut self, new_span: Span) -> Self {
+        match &mut self {
+            Ast::List(l) => Rc::make_mut(l).span = new_span,
+            Ast::Leaf(_, span) => *span = new_span,
+        }
+        self
     }
-    pub(crate) fn get_function(&self) -> Result<Rc<dyn Fn(Vec<Ast>) -> Result<Ast, Error>>, Error> {
-        if let Ast::Leaf(l) = self {
+    pub(crate) fn get_function(&self) -> Result<&LFunction, Error> {
+        if let Ast::Leaf(l, _) = self {
             if let AstLeaf::Function(lf) = l {
-                return Ok(lf.f.clone());
+                return Ok(lf);
             }
         }
         Err(Error::EvalError("not a function".to_owned()))
     }
 
     pub(crate) fn get_leaf(&self) -> Result<&AstLeaf, Error> {
-        if let Ast::Leaf(l) = self {
+        if let Ast::Leaf(l, _) = self {
             Ok(l)
         } else {
             Err(Error::EvalError("not a leaf".to_owned()))
@@ -137,7 +329,7 @@ impl Ast {
     }
     pub(crate) fn get_any_list_mut(&mut self) -> Result<&mut Vec<Ast>, Error> {
         if let Ast::List(l) = self {
-            Ok(&mut l.list)
+            Ok(&mut Rc::make_mut(l).list)
         } else {
             Err(Error::EvalError("should be a list".to_owned()))
         }
@@ -158,6 +350,8 @@ fn get_list_type(lex: Token) -> Option<ListType> {
         LeftParen => Some(Parens),
         LeftBrace => Some(Braces),
         LeftBracket => Some(Brackets),
+        LeftSetBrace => Some(Set),
+        LeftFnParen => Some(Parens),
         _ => None,
     }
 }
@@ -167,71 +361,118 @@ fn does_terminate(lex: Token, list_type: ListType) -> bool {
     use Token::*;
     match lex {
         RightParen => list_type == Parens,
-        RightBrace => list_type == Braces,
+        RightBrace => list_type == Braces || list_type == Set,
         RightBracket => list_type == Brackets,
         _ => false,
     }
 }
 
-pub(crate) fn parse(lexemes: Vec<Token>) -> Result<Vec<Ast>, Error> {
-    let mut stack_parens: Vec<ListType> = Vec::new();
-    let mut stack_lists: Vec<Vec<Ast>> = Vec::new();
-    let mut current_list: Vec<Ast> = Vec::new();
-    for l in lexemes.into_iter() {
+pub(crate) fn parse(lexemes: Vec<(Token, Span)>, config: &ReaderConfig) -> Result<Vec<Ast>, Error> {
+    let mut stack_parens: Vec<(ListType, Span)> = Vec::new();
+    let mut stack_lists: Vec<Forms> = Vec::new();
+    let mut current_list = Forms::new(config);
+    // How many lists were open outside the `#(` being read, if any.
+    let mut lambda: Option<usize> = None;
+    for (l, span) in lexemes.into_iter() {
         match l {
-            Token::String(x) => current_list.push(Ast::string(x)),
-            Token::Int(x) => current_list.push(Ast::int(x)),
-            Token::Float(x) => current_list.push(Ast::float(x)),
-            Token::Symbol(x) => current_list.push(Ast::symbol(x)),
-            Token::LeftParen | Token::LeftBrace | Token::LeftBracket => {
-                stack_parens.push(get_list_type(l).expect("Trust me"));
-                stack_lists.push(mem::replace(&mut current_list, Vec::new()));
+            Token::String(x) => current_list.push(Ast::string(x).with_span(span))?,
+            Token::Regex(x) => {
+                let pattern = Pattern::new(&x).map_err(|e| e.at(span))?;
+                current_list.push(Ast::leaf(AstLeaf::Regex(pattern)).with_span(span))?
+            }
+            Token::Int(x) => current_list.push(Ast::int(x).with_span(span))?,
+            Token::Float(x) => current_list.push(Ast::float(x).with_span(span))?,
+            Token::Symbol(x) => current_list.push_symbol(x, span)?,
+            Token::Keyword(x) => current_list.push(Ast::keyword(x).with_span(span))?,
+            Token::Bool(x) => current_list.push(Ast::bool(x).with_span(span))?,
+            Token::Nil => current_list.push(Ast::nil().with_span(span))?,
+            Token::Discard => current_list.discard(span),
+            Token::LeftFnParen if lambda.is_some() => {
+                return Err(Error::ReaderMacroError("`#(` cannot be nested".to_owned()).at(span))
+            }
+            Token::LeftParen
+            | Token::LeftBrace
+            | Token::LeftBracket
+            | Token::LeftSetBrace
+            | Token::LeftFnParen => {
+                if l == Token::LeftFnParen {
+                    lambda = Some(stack_parens.len());
+                }
+                stack_parens.push((get_list_type(l).expect("Trust me"), span));
+                stack_lists.push(mem::replace(&mut current_list, Forms::new(config)));
             }
             Token::RightParen | Token::RightBrace | Token::RightBracket => {
-                let parent_list = stack_lists.pop().unwrap();
-                let mut child_list = mem::replace(&mut current_list, parent_list);
-                let list_type = stack_parens.pop().unwrap();
+                let (list_type, open_span) = match stack_parens.pop() {
+                    Some(opened) => opened,
+                    None => return Err(Error::Unbalanced.at(span)),
+                };
+                let parent_list = stack_lists.pop().expect("pushed with stack_parens");
                 if !(does_terminate(l, list_type)) {
-                    return Err(Error::Unbalanced);
+                    return Err(Error::Unbalanced.at(span));
+                }
+                let child_list = mem::replace(&mut current_list, parent_list).finish()?;
+                if list_type == ListType::Braces && child_list.len() % 2 != 0 {
+                    return Err(Error::OddMap(child_list.len()).at(open_span.to(span)));
+                }
+                let mut list = Ast::list(list_type, child_list).with_span(open_span.to(span));
+                if lambda == Some(stack_parens.len()) {
+                    lambda = None;
+                    list = reader_macros::anonymous_fn(list, open_span)?;
                 }
-                reader_macros::apply(&mut child_list);
-                current_list.push(Ast::List(AstList {
-                    list_type: list_type,
-                    list: child_list,
-                }));
+                current_list.push(list)?;
             }
         }
     }
-    if stack_parens.is_empty() {
-        reader_macros::apply(&mut current_list);
-        Ok(current_list)
+    if let Some((list_type, open_span)) = stack_parens.pop() {
+        let closer = match list_type {
+            ListType::Parens => ')',
+            ListType::Brackets => ']',
+            ListType::Braces | ListType::Set => '}',
+        };
+        Err(Error::Incomplete(format!("expected `{}`", closer)).at(open_span))
+    } else if let Some((prefix, span)) = current_list.dangling_prefix() {
+        Err(Error::Incomplete(format!("expected a form after `{}`", prefix)).at(span))
     } else {
-        Err(Error::Unbalanced)
+        current_list.finish()
     }
 }
 
-pub(crate) fn read(s: String) -> Result<Ast, Error> {
-    let lex = Lexer::new();
-    let tokens = lex.tokenize(&s).map_err(|_| Error::EOF)?;
-    let mut ast_top: Vec<Ast> = parse(tokens)?;
-    if ast_top.is_empty() {
-        Ok(Default::default())
-    } else {
-        Ok(ast_top.pop().unwrap())
+/// Whether `s` is the beginning of valid input that has not been finished
+/// yet: an unclosed list, an open string or a trailing prefix of one of
+/// the reader macros of `config`.
+pub(crate) fn is_incomplete(s: &str, config: &ReaderConfig) -> bool {
+    match read(s, config) {
+        Err(e) => e.is_incomplete(),
+        Ok(_) => false,
     }
 }
 
+/// Reads every top-level form in `s`, in order, with the default reader
+/// macros.
+pub(crate) fn read_all(s: &str) -> Result<Vec<Ast>, Error> {
+    read(s, &ReaderConfig::default())
+}
+
+/// Reads every top-level form in `s`, in order, expanding the reader
+/// macros of `config`.
+pub(crate) fn read(s: &str, config: &ReaderConfig) -> Result<Vec<Ast>, Error> {
+    let lex = Lexer::with_prefixes(config.prefixes());
+    let tokens = lex.tokenize_spanned(s)?;
+    parse(tokens, config)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::time::Instant;
 
     #[test]
     fn macro_with_meta() {
         let lex = Lexer::new();
         let tokens = lex
-            .tokenize("^{yolo swag} (+ '1 3 ^{300 bucks} [420  ^{top kek} (+ 1 322)] 3 7) ")
+            .tokenize_spanned("^{yolo swag} (+ '1 3 ^{300 bucks} [420  ^{top kek} (+ 1 322)] 3 7) ")
             .unwrap();
-        let mut ast_top: Vec<Ast> = parse(tokens).unwrap();
+        let mut ast_top: Vec<Ast> = parse(tokens, &ReaderConfig::default()).unwrap();
         assert_eq!(
             ast_top,
             vec![Ast::parens(vec![
@@ -269,4 +510,264 @@ mod test {
             ])]
         );
     }
+
+    #[test]
+    fn nested_prefixes() {
+        let forms = read_all("`~a '`(~@b) @'c").unwrap();
+        let printed: Vec<String> = forms.iter().map(|f| f.pr_str(true)).collect();
+        assert_eq!(
+            printed,
+            vec![
+                "(quasiquote (unquote a))",
+                "(quote (quasiquote ((splice-unquote b))))",
+                "(deref (quote c))",
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_prefixes() {
+        let dangling = |source: &str| {
+            let err = read_all(source).err().unwrap();
+            let span = err.span().unwrap();
+            (
+                err.is_incomplete(),
+                err.kind().to_string(),
+                span.start,
+                span.end,
+            )
+        };
+        let after = |prefix: &str| format!("expected a form after `{}`", prefix);
+        assert_eq!(dangling("(+ 1 ~@)"), (false, after("~@"), 5, 7));
+        assert_eq!(dangling("[^{m 1}]"), (false, after("^"), 1, 2));
+        assert_eq!(
+            dangling("(f ^x)"),
+            (false, "expected a map after `^`".to_owned(), 3, 5)
+        );
+        assert_eq!(
+            dangling("'"),
+            (
+                true,
+                format!("unexpected end of input, {}", after("'")),
+                0,
+                1
+            )
+        );
+        assert!(dangling("(a) ^{m 1}").0);
+    }
+
+    #[test]
+    fn dispatch_forms() {
+        let printed = |source: &str| {
+            let forms = read_all(source).unwrap();
+            forms.iter().map(|f| f.pr_str(true)).collect::<Vec<_>>()
+        };
+        assert_eq!(printed("#{1 #{}} #_x y"), vec!["#{1 #{}}", "y"]);
+        assert_eq!(printed("[#_ #_ a b c '#_d e]"), vec!["[c (quote e)]"]);
+        assert_eq!(printed("{:a 1 #_:b}"), vec!["{:a 1}"]);
+        assert_eq!(
+            printed("#(+ % %3 (f %1)) #(apply f %&) #(g)"),
+            vec![
+                "(fn* [%1 %2 %3] (+ %1 %3 (f %1)))",
+                "(fn* [& %&] (apply f %&))",
+                "(fn* [] (g))",
+            ]
+        );
+        let err = read_all("#(f #(g))").err().unwrap();
+        assert_eq!(err.span().map(|s| (s.start, s.end)), Some((4, 6)));
+        let err = read_all("(f #_)").err().unwrap();
+        assert_eq!(err.kind().to_string(), "expected a form after `#_`");
+        assert!(read_all("#{1").err().unwrap().is_incomplete());
+        assert!(read_all("#_").err().unwrap().is_incomplete());
+    }
+
+    #[test]
+    fn regex_literals() {
+        let forms = read_all(r#"[#"\d+" #"a\"b"]"#).unwrap();
+        assert_eq!(forms[0].pr_str(true), r#"[#"\d+" #"a\"b"]"#);
+        match &forms[0].get_any_list().unwrap()[1] {
+            Ast::Leaf(AstLeaf::Regex(p), _) => {
+                assert_eq!(p.as_str(), r#"a"b"#);
+                assert!(p.regex.is_match(r#"xa"b"#));
+            }
+            other => panic!("not a regex: {:?}", other),
+        }
+        let err = read_all("(f #\"(\")").err().unwrap();
+        assert!(matches!(err.kind(), Error::InvalidRegex(_)));
+        assert_eq!(err.span().map(|s| (s.start, s.end)), Some((3, 7)));
+    }
+
+    #[test]
+    fn registered_prefixes() {
+        let mut config = ReaderConfig::default();
+        config.register("#'", "var").unwrap();
+        config.register("@", "force").unwrap();
+        let forms = read("#'x (f #'(g) #'#'y) @a #'x#'y", &config).unwrap();
+        let printed: Vec<String> = forms.iter().map(|f| f.pr_str(true)).collect();
+        assert_eq!(
+            printed,
+            vec![
+                "(var x)",
+                "(f (var (g)) (var (var y)))",
+                "(force a)",
+                "(var x#'y)"
+            ]
+        );
+        assert_eq!((forms[0].span().start, forms[0].span().end), (0, 3));
+        assert!(config.register("", "var").is_err());
+        assert!(config.register("#(", "var").is_err());
+        assert!(config.register("1#", "var").is_err());
+        for symbol_start in &["v", "-", ":", "a'", "#_", "#a", "#''", "'a"] {
+            assert!(
+                config.register(symbol_start, "var").is_err(),
+                "{} was accepted",
+                symbol_start
+            );
+        }
+        config.register("~~", "twice").unwrap();
+        let forms = read("(- 1 -2) :k v ~~x", &config).unwrap();
+        assert_eq!(forms[0].pr_str(true), "(- 1 -2)");
+        assert_eq!(forms[3].pr_str(true), "(twice x)");
+    }
+
+    /// A benchmark rather than a check, since every element carries a
+    /// reader macro. Run it with
+    /// `cargo test --release --bin step1_read_print quoted_vector -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_quoted_vector() {
+        let elements = 100_000;
+        let source = format!("[{}]", "'a ".repeat(elements));
+        let start = Instant::now();
+        let forms = read_all(&source).unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(forms[0].get_any_list().unwrap().len(), elements);
+        println!("read {} quoted elements in {:?}", elements, elapsed);
+    }
+
+    #[test]
+    fn form_spans() {
+        let lex = Lexer::new();
+        let tokens = lex.tokenize_spanned("(a\n [b 'c])").unwrap();
+        let ast_top = parse(tokens, &ReaderConfig::default()).unwrap();
+        let outer = ast_top[0].get_any_list().unwrap();
+        let inner = outer[1].get_any_list().unwrap();
+        assert_eq!((ast_top[0].span().start, ast_top[0].span().end), (0, 11));
+        assert_eq!((outer[0].span().line, outer[0].span().column), (1, 2));
+        assert_eq!((outer[1].span().start, outer[1].span().end), (4, 10));
+        assert_eq!((outer[1].span().line, outer[1].span().column), (2, 2));
+        assert_eq!((inner[1].span().start, inner[1].span().end), (7, 9));
+    }
+
+    #[test]
+    fn unbalanced_span() {
+        let lex = Lexer::new();
+        let tokens = lex.tokenize_spanned("(1\n 2]").unwrap();
+        let err = parse(tokens, &ReaderConfig::default()).err().unwrap();
+        assert_eq!(err.kind(), &Error::Unbalanced);
+        let span = err.span().unwrap();
+        assert_eq!((span.start, span.line, span.column), (5, 2, 3));
+    }
+
+    #[test]
+    fn odd_map() {
+        let err = read_all("(f\n {:a 1 :b})").err().unwrap();
+        assert_eq!(err.kind(), &Error::OddMap(3));
+        let span = err.span().unwrap();
+        assert_eq!((span.start, span.end, span.line), (4, 13, 2));
+        assert!(read_all("{:a 'b}").is_ok());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let mut config = ReaderConfig::default();
+        assert!(is_incomplete("(def! x (+ 1", &config));
+        assert!(is_incomplete("[1 {:a", &config));
+        assert!(is_incomplete("(str \"abc", &config));
+        assert!(is_incomplete("(str \"abc\\", &config));
+        assert!(is_incomplete("(re-find #\"\\d+", &config));
+        assert!(is_incomplete("#\"a\\", &config));
+        assert!(is_incomplete("'", &config));
+        assert!(is_incomplete("(+ 1 2) ~@", &config));
+        assert!(is_incomplete("^{a 1}", &config));
+        assert!(!is_incomplete("(+ 1 2)", &config));
+        assert!(!is_incomplete("(]", &config));
+        assert!(!is_incomplete("(+ 1 2))", &config));
+        assert!(!is_incomplete("1337s", &config));
+        assert!(!is_incomplete("(+ 1 ~@)", &config));
+        assert!(!is_incomplete("#'", &config));
+        config.register("#'", "var").unwrap();
+        assert!(is_incomplete("#'", &config));
+    }
+
+    #[test]
+    fn read_all_forms() {
+        let forms = read_all("(def! a 1) (def! b 2)\n3").unwrap();
+        assert_eq!(
+            forms,
+            vec![
+                Ast::parens(vec![
+                    Ast::symbol("def!".to_owned()),
+                    Ast::symbol("a".to_owned()),
+                    Ast::int(1)
+                ]),
+                Ast::parens(vec![
+                    Ast::symbol("def!".to_owned()),
+                    Ast::symbol("b".to_owned()),
+                    Ast::int(2)
+                ]),
+                Ast::int(3),
+            ]
+        );
+        assert_eq!(read_all("  ; nothing here").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn print_read_round_trip() {
+        let source = r#"("a\"b\\c\nd" [:k "\u{263A}"] nil)"#;
+        let forms = read_all(source).unwrap();
+        assert_eq!(
+            forms[0].pr_str(true),
+            "(\"a\\\"b\\\\c\\nd\" [:k \"\u{263A}\"] nil)"
+        );
+        assert_eq!(forms[0].pr_str(false), "(a\"b\\c\nd [:k \u{263A}] nil)");
+        assert_eq!(read_all(&forms[0].pr_str(true)).unwrap(), forms);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 100_000;
+        let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let forms = read_all(&source).unwrap();
+        assert_eq!(forms[0].pr_str(true), source);
+    }
+
+    #[test]
+    fn arity_messages() {
+        assert_eq!(Arity::exactly(2).check("mod", 2), Ok(()));
+        assert_eq!(Arity::at_least(1).check("-", 5), Ok(()));
+        assert_eq!(
+            Arity::exactly(2).check("mod", 3),
+            Err(Error::EvalError(
+                "`mod` expects 2 arguments, got 3".to_owned()
+            ))
+        );
+        assert_eq!(
+            Arity::exactly(1).check("f", 0),
+            Err(Error::EvalError("`f` expects 1 argument, got 0".to_owned()))
+        );
+        assert_eq!(
+            Arity::at_least(1).check("-", 0),
+            Err(Error::EvalError(
+                "`-` expects at least 1 argument, got 0".to_owned()
+            ))
+        );
+        assert_eq!(Arity::between(2, 3).check("if", 3), Ok(()));
+        assert_eq!(
+            Arity::between(2, 3).check("if", 4),
+            Err(Error::EvalError(
+                "`if` expects at most 3 arguments, got 4".to_owned()
+            ))
+        );
+    }
 }
@@ -1,128 +1,299 @@
-use crate::reader::{Ast, AstLeaf, AstList, ListType};
-use std::mem;
+use crate::depth;
+use crate::error::Error;
+use crate::lexer::Span;
+use crate::reader::{Ast, AstLeaf, ListType};
 
-pub(crate) trait ReaderMacro {
-    const WINDOW: usize;
-    fn process_ast_slice(ast: &mut [Ast]) -> bool;
+/// Prefixes that wrap the form after them as `(head form)`.
+const SUBSTITUTIONS: [(&str, &str); 5] = [
+    ("'", "quote"),
+    ("`", "quasiquote"),
+    ("@", "deref"),
+    ("~", "unquote"),
+    ("~@", "splice-unquote"),
+];
 
-    fn process_ast(ast: &mut Vec<Ast>) {
-        let mut i = 0;
+/// `^{meta} form` reads as `(with-meta form {meta})`.
+const WITH_META: (&str, &str) = ("^", "with-meta");
 
-        while ast.len() >= Self::WINDOW && i <= ast.len() - Self::WINDOW {
-            let changed = Self::process_ast_slice(&mut ast[i..i + Self::WINDOW]);
-            if changed {
-                for _ in 0..Self::WINDOW - 1 {
-                    ast.remove(i + 1);
-                }
-                i = 0;
-            } else {
-                i += 1;
-            }
-        }
+/// `#_form` reads as nothing at all.
+const DISCARD: &str = "#_";
+
+/// What the form(s) after a reader macro prefix read as.
+#[derive(Debug, Clone, PartialEq)]
+enum Expansion {
+    /// `prefix form` reads as `(head form)`.
+    Wrap(String),
+    /// `prefix {meta} form` reads as `(head form {meta})`.
+    WithMeta(String),
+}
+
+/// The reader macros that `reader::read` expands, by prefix. The default
+/// holds the quoting prefixes, `@` and `^`; more are added with
+/// [`ReaderConfig::register`].
+#[derive(Debug, Clone)]
+pub(crate) struct ReaderConfig {
+    macros: Vec<(String, Expansion)>,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        let mut macros: Vec<_> = SUBSTITUTIONS
+            .iter()
+            .map(|&(prefix, head)| (prefix.to_owned(), Expansion::Wrap(head.to_owned())))
+            .collect();
+        let (prefix, head) = WITH_META;
+        macros.push((prefix.to_owned(), Expansion::WithMeta(head.to_owned())));
+        ReaderConfig { macros }
     }
 }
 
-pub(crate) struct WithMeta;
-pub(crate) struct Quote;
-pub(crate) struct QuasiQuote;
-pub(crate) struct Deref;
-pub(crate) struct Unquote;
-pub(crate) struct SpliceUnquote;
-
-impl ReaderMacro for WithMeta {
-    const WINDOW: usize = 3;
-
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        let meta_symbol = &ast[0];
-        let meta_info = &ast[1];
-
-        match (meta_symbol, meta_info) {
-            (
-                Ast::Leaf(AstLeaf::Symbol(ref meta_char)),
-                Ast::List(AstList {
-                    list_type: ListType::Braces,
-                    list: _,
-                }),
-            ) if meta_char == "^" => {
-                let replace = Ast::List(AstList {
-                    list_type: ListType::Parens,
-                    list: vec![
-                        Ast::symbol("with-meta".to_owned()),
-                        mem::replace(&mut ast[2], Default::default()),
-                        mem::replace(&mut ast[1], Default::default()),
-                    ],
-                });
-                mem::replace(&mut ast[0], replace);
-                true
-            }
-            _ => false,
-        }
+/// Characters that only ever start a reader macro, never an ordinary
+/// token.
+const MACRO_CHARS: &str = "'`~@^";
+
+/// Whether `prefix` can be told apart from the start of an ordinary token:
+/// either it is made of `MACRO_CHARS`, or it is `#` and one punctuation
+/// character that is not a delimiter or taken by the lexer, such as `#'`.
+fn is_valid_prefix(prefix: &str) -> bool {
+    let mut chars = prefix.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('#'), Some(c), None) => c.is_ascii_punctuation() && !"()[]{}\";,_#".contains(c),
+        (Some(_), _, _) => prefix.chars().all(|c| MACRO_CHARS.contains(c)),
+        (None, _, _) => false,
     }
 }
 
-fn simple_sub(ast: &mut [Ast], matcher: &str, replacement: &str) -> bool {
-    let reader_symbol = &ast[0];
-
-    match reader_symbol {
-        Ast::Leaf(AstLeaf::Symbol(ref reader_str)) if reader_str == matcher => {
-            let replace = Ast::List(AstList {
-                list_type: ListType::Parens,
-                list: vec![
-                    Ast::symbol(replacement.to_owned()),
-                    mem::replace(&mut ast[1], Default::default()),
-                ],
-            });
-            mem::replace(&mut ast[0], replace);
-            true
+impl ReaderConfig {
+    /// Makes `prefix form` read as `(head form)`, replacing whatever
+    /// `prefix` read as before. The prefix is recognised wherever a token
+    /// may start, so it must be one that no ordinary token starts with.
+    pub(crate) fn register(&mut self, prefix: &str, head: &str) -> Result<(), Error> {
+        let valid = is_valid_prefix(prefix);
+        if !valid {
+            return Err(Error::EvalError(format!(
+                "`{}` cannot be used as a reader macro prefix",
+                prefix
+            )));
         }
-        _ => false,
+        let expansion = Expansion::Wrap(head.to_owned());
+        match self.macros.iter_mut().find(|(p, _)| p == prefix) {
+            Some(existing) => existing.1 = expansion,
+            None => self.macros.push((prefix.to_owned(), expansion)),
+        }
+        Ok(())
     }
-}
 
-impl ReaderMacro for Quote {
-    const WINDOW: usize = 2;
+    /// Every prefix, for the lexer to split off the start of a token.
+    pub(crate) fn prefixes(&self) -> Vec<String> {
+        self.macros
+            .iter()
+            .map(|(prefix, _)| prefix.clone())
+            .collect()
+    }
 
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        simple_sub(ast, "'", "quote")
+    fn find(&self, symbol: &str) -> Option<(&str, &Expansion)> {
+        self.macros
+            .iter()
+            .find(|(prefix, _)| prefix == symbol)
+            .map(|(prefix, expansion)| (prefix.as_str(), expansion))
     }
 }
 
-impl ReaderMacro for QuasiQuote {
-    const WINDOW: usize = 2;
+/// A reader macro prefix that is still waiting for the form(s) after it.
+enum Pending<'a> {
+    Wrap {
+        prefix: &'a str,
+        head: &'a str,
+        span: Span,
+    },
+    /// `^`, first waiting for its map and then for the form it applies to.
+    Meta {
+        prefix: &'a str,
+        head: &'a str,
+        span: Span,
+        meta: Option<Ast>,
+    },
+    /// `#_`, waiting for the form it drops.
+    Discard { span: Span },
+}
 
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        simple_sub(ast, "`", "quasiquote")
-    }
+/// The forms of one list as they are read. Prefixes wait on a stack until
+/// the form after them is complete, so every form is handled once and
+/// nested prefixes such as `` `~a `` apply from the inside out.
+pub(crate) struct Forms<'a> {
+    config: &'a ReaderConfig,
+    forms: Vec<Ast>,
+    pending: Vec<Pending<'a>>,
 }
 
-impl ReaderMacro for Deref {
-    const WINDOW: usize = 2;
+fn call(head: &str, span: Span, args: Vec<Ast>) -> Ast {
+    let end = args.first().map_or(span, Ast::span);
+    let mut forms = vec![Ast::symbol(head.to_owned()).with_span(span)];
+    forms.extend(args);
+    Ast::parens(forms).with_span(span.to(end))
+}
 
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        simple_sub(ast, "@", "deref")
+fn is_map(ast: &Ast) -> bool {
+    matches!(ast, Ast::List(l) if l.list_type == ListType::Braces)
+}
+
+impl<'a> Forms<'a> {
+    pub(crate) fn new(config: &'a ReaderConfig) -> Self {
+        Forms {
+            config,
+            forms: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds a symbol, which may be a prefix for the forms after it.
+    pub(crate) fn push_symbol(&mut self, symbol: String, span: Span) -> Result<(), Error> {
+        match self.config.find(&symbol) {
+            Some((prefix, Expansion::Wrap(head))) => {
+                self.pending.push(Pending::Wrap { prefix, head, span })
+            }
+            Some((prefix, Expansion::WithMeta(head))) => self.pending.push(Pending::Meta {
+                prefix,
+                head,
+                span,
+                meta: None,
+            }),
+            None => return self.push(Ast::symbol(symbol).with_span(span)),
+        }
+        Ok(())
+    }
+
+    /// Starts a `#_`, which drops the next complete form.
+    pub(crate) fn discard(&mut self, span: Span) {
+        self.pending.push(Pending::Discard { span });
+    }
+
+    /// Adds a complete form, applying the prefixes that were waiting for it.
+    pub(crate) fn push(&mut self, mut form: Ast) -> Result<(), Error> {
+        while let Some(pending) = self.pending.pop() {
+            form = match pending {
+                Pending::Wrap { head, span, .. } => call(head, span, vec![form]),
+                Pending::Discard { .. } => return Ok(()),
+                Pending::Meta {
+                    head,
+                    span,
+                    meta: Some(meta),
+                    ..
+                } => call(head, span, vec![form, meta]),
+                Pending::Meta {
+                    prefix,
+                    head,
+                    span,
+                    meta: None,
+                } if is_map(&form) => {
+                    let meta = Some(form);
+                    self.pending.push(Pending::Meta {
+                        prefix,
+                        head,
+                        span,
+                        meta,
+                    });
+                    return Ok(());
+                }
+                Pending::Meta {
+                    prefix,
+                    span,
+                    meta: None,
+                    ..
+                } => {
+                    let message = format!("expected a map after `{}`", prefix);
+                    return Err(Error::ReaderMacroError(message).at(span.to(form.span())));
+                }
+            };
+        }
+        self.forms.push(form);
+        Ok(())
+    }
+
+    /// The last prefix at the end of the input that is still waiting for
+    /// the form(s) it applies to.
+    pub(crate) fn dangling_prefix(&self) -> Option<(&'a str, Span)> {
+        self.pending.last().map(|pending| match pending {
+            Pending::Wrap { prefix, span, .. } | Pending::Meta { prefix, span, .. } => {
+                (*prefix, *span)
+            }
+            Pending::Discard { span } => (DISCARD, *span),
+        })
+    }
+
+    /// The forms of a list that ends here, which fails if a prefix in it
+    /// has nothing left to apply to.
+    pub(crate) fn finish(self) -> Result<Vec<Ast>, Error> {
+        match self.dangling_prefix() {
+            Some((prefix, span)) => {
+                Err(Error::ReaderMacroError(format!("expected a form after `{}`", prefix)).at(span))
+            }
+            None => Ok(self.forms),
+        }
     }
 }
 
-impl ReaderMacro for Unquote {
-    const WINDOW: usize = 2;
+/// The parameter an argument literal of `#(...)` stands for: `%` and `%1`
+/// are the first, `%2` the second and so on, and `%&` takes the rest.
+enum Arg {
+    Nth(usize),
+    Rest,
+}
 
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        simple_sub(ast, "~", "unquote")
+fn arg_literal(symbol: &str) -> Option<Arg> {
+    match symbol.strip_prefix('%')? {
+        "" => Some(Arg::Nth(1)),
+        "&" => Some(Arg::Rest),
+        n => n.parse().ok().filter(|&n| n > 0).map(Arg::Nth),
     }
 }
-impl ReaderMacro for SpliceUnquote {
-    const WINDOW: usize = 2;
 
-    fn process_ast_slice(ast: &mut [Ast]) -> bool {
-        simple_sub(ast, "~@", "splice-unquote")
+/// `form` with `%` spelled `%1`, noting the highest argument used in `count`
+/// and whether `%&` was in `rest`.
+fn number_args(form: &Ast, count: &mut usize, rest: &mut bool) -> Result<Ast, Error> {
+    let _depth = depth::enter()?;
+    match form {
+        Ast::List(l) => {
+            let forms = l
+                .list
+                .iter()
+                .map(|f| number_args(f, count, rest))
+                .collect::<Result<_, _>>()?;
+            Ok(Ast::list(l.list_type, forms).with_span(l.span))
+        }
+        Ast::Leaf(AstLeaf::Symbol(s), span) => match arg_literal(s) {
+            Some(Arg::Nth(n)) => {
+                *count = (*count).max(n);
+                Ok(Ast::symbol(format!("%{}", n)).with_span(*span))
+            }
+            Some(Arg::Rest) => {
+                *rest = true;
+                Ok(form.clone())
+            }
+            None => Ok(form.clone()),
+        },
+        Ast::Leaf(..) => Ok(form.clone()),
     }
 }
 
-pub(crate) fn apply(ast_list: &mut Vec<Ast>) {
-    WithMeta::process_ast(ast_list);
-    Quote::process_ast(ast_list);
-    QuasiQuote::process_ast(ast_list);
-    Deref::process_ast(ast_list);
-    Unquote::process_ast(ast_list);
-    SpliceUnquote::process_ast(ast_list);
+/// `#(f % %2)`, read as the list `body`, as `(fn* [%1 %2] (f %1 %2))`:
+/// one parameter for each argument up to the highest one used, plus
+/// `& %&` if the rest are used.
+pub(crate) fn anonymous_fn(body: Ast, open: Span) -> Result<Ast, Error> {
+    let (mut count, mut rest) = (0, false);
+    let body = number_args(&body, &mut count, &mut rest)?;
+    let mut params: Vec<Ast> = (1..=count)
+        .map(|n| Ast::symbol(format!("%{}", n)).with_span(open))
+        .collect();
+    if rest {
+        params.push(Ast::symbol("&".to_owned()).with_span(open));
+        params.push(Ast::symbol("%&".to_owned()).with_span(open));
+    }
+    let span = body.span();
+    Ok(Ast::parens(vec![
+        Ast::symbol("fn*".to_owned()).with_span(open),
+        Ast::brackets(params).with_span(open),
+        body,
+    ])
+    .with_span(span))
 }
@@ -0,0 +1,116 @@
+use crate::reader;
+use crate::reader_macros::ReaderConfig;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+const HISTORY: &str = "history.txt";
+const PROMPT: &str = "user> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+
+/// `line` without its line ending, which the editor keeps when input is
+/// not a terminal.
+fn chomp(mut line: String) -> String {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+pub(crate) struct Readline {
+    editor: Editor<()>,
+    /// Whether entries are loaded from and saved to `HISTORY`.
+    history: bool,
+}
+
+impl Readline {
+    pub(crate) fn new() -> Self {
+        let mut editor = Editor::<()>::new();
+        if editor.load_history(HISTORY).is_err() {
+            println!("No previous history.");
+        }
+        Readline {
+            editor,
+            history: true,
+        }
+    }
+
+    /// An editor for a script's `readline` calls, which neither reads nor
+    /// writes the history file and prints nothing of its own.
+    pub(crate) fn without_history() -> Self {
+        Readline {
+            editor: Editor::<()>::new(),
+            history: false,
+        }
+    }
+
+    /// Reads one entry from the user. While the buffered input is an
+    /// unfinished form, keeps reading more lines with a continuation prompt.
+    /// Interrupting a continuation discards the buffered input; `None` means
+    /// the user is done.
+    pub(crate) fn read_input(&mut self) -> Option<String> {
+        self.read_input_with(&ReaderConfig::default())
+    }
+
+    /// Like `read_input`, for input read with the reader macros of `config`.
+    pub(crate) fn read_input_with(&mut self, config: &ReaderConfig) -> Option<String> {
+        'entry: loop {
+            let mut input = self.read_line(PROMPT)?;
+            while reader::is_incomplete(&input, config) {
+                match self.editor.readline(CONTINUATION_PROMPT) {
+                    Ok(line) => {
+                        input.push('\n');
+                        input.push_str(&chomp(line));
+                    }
+                    Err(ReadlineError::Interrupted) => continue 'entry,
+                    Err(_) => break,
+                }
+            }
+            self.editor.add_history_entry(input.as_str());
+            return Some(input);
+        }
+    }
+
+    /// Reads a single line with `prompt`, as the `readline` builtin does.
+    pub(crate) fn read_entry(&mut self, prompt: &str) -> Option<String> {
+        let line = self.read_line(prompt)?;
+        self.editor.add_history_entry(line.as_str());
+        Some(line)
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Some(chomp(line)),
+            Err(ReadlineError::Interrupted) => None,
+            Err(ReadlineError::Eof) => None,
+            Err(err) => {
+                println!("Error: {:?}", err);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn save_history(&mut self) {
+        if !self.history {
+            return;
+        }
+        if let Err(err) = self.editor.save_history(HISTORY) {
+            println!("Could not save history: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(chomp("hello\n".to_owned()), "hello");
+        assert_eq!(chomp("hello\r\n".to_owned()), "hello");
+        assert_eq!(chomp("hello\r".to_owned()), "hello\r");
+        assert_eq!(chomp("".to_owned()), "");
+    }
+}
@@ -1,24 +1,18 @@
+mod depth;
+mod diagnostic;
+mod error;
 mod formatter;
 mod lexer;
 mod reader;
 mod reader_macros;
 
+use crate::error::Error;
 use crate::reader::Ast;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-#[derive(Debug, PartialEq)]
-pub enum Error {
-    TransitionError(String),
-    TokenTerminationError(String),
-    Unbalanced,
-    EOF,
-    ReaderMacroError,
-    EvalError(String),
-}
-
-fn read(s: String) -> Result<Ast, Error> {
-    reader::read(s)
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
 }
 
 fn eval(ast: Ast) -> Ast {
@@ -26,15 +20,15 @@ fn eval(ast: Ast) -> Ast {
 }
 
 fn print(ast: Ast) -> String {
-    format!("{}", ast)
+    ast.pr_str(true)
 }
 
-fn repl(s: String) -> String {
-    if let Ok(r) = read(s) {
-        print(eval(r))
-    } else {
-        "unbalanced".to_owned()
+/// Reads and prints every form in `s`.
+fn rep(s: &str) -> Result<(), Error> {
+    for ast in read(s)? {
+        println!("{}", print(eval(ast)));
     }
+    Ok(())
 }
 
 fn main() {
@@ -47,7 +41,9 @@ fn main() {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                println!("{}", repl(line));
+                if let Err(e) = error::catch_panic(|| rep(&line)) {
+                    println!("{}", diagnostic::render(&line, &e));
+                }
             }
             Err(ReadlineError::Interrupted) => break,
             Err(ReadlineError::Eof) => break,
@@ -1,73 +1,57 @@
+mod depth;
+mod diagnostic;
+mod error;
 mod formatter;
 mod lexer;
+mod numeric;
 mod reader;
 mod reader_macros;
 
-use crate::reader::{Ast, AstLeaf, ListType};
+use crate::error::Error;
+use crate::numeric::Number;
+use crate::reader::{Arity, Ast, AstLeaf, ListType};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use std::collections::HashMap;
-use thiserror::Error;
+use std::rc::Rc;
 
-#[derive(Error, Debug, PartialEq)]
-pub enum Error {
-    #[error("transition error `{0}`")]
-    TransitionError(String),
-    #[error("token termination error `{0}`")]
-    TokenTerminationError(String),
-    #[error("unbalanced parens")]
-    Unbalanced,
-    #[error("eof while parsing a string")]
-    EOF,
-    #[error("reader macro error")]
-    ReaderMacroError,
-    #[error("eval error `{0}`")]
-    EvalError(String),
-}
-
-struct Env(HashMap<String, Box<dyn Fn(Vec<f32>) -> f32>>);
+type Builtin = Box<dyn Fn(Vec<Number>) -> Result<Number, Error>>;
 
-fn read(s: String) -> Result<Ast, Error> {
-    reader::read(s)
-}
+struct Env(HashMap<String, (Arity, Builtin)>);
 
-fn ast_to_f32(ast: Ast) -> Result<f32, Error> {
-    if let Ast::Leaf(leaf) = ast {
-        return match leaf {
-            AstLeaf::Float(f) => Ok(f),
-            AstLeaf::Int(i) => Ok(i as f32),
-            _ => Err(Error::EvalError("cannot convert to f32".to_string())),
-        };
-    }
-    Err(Error::EvalError("not a leaf".to_string()))
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
 }
 
 fn eval(ast: &mut Ast, env: &Env) -> Result<(), Error> {
     match ast {
-        Ast::Leaf(leaf) => Ok(()),
+        Ast::Leaf(_, _) => Ok(()),
         Ast::List(list) => {
+            let list = Rc::make_mut(list);
+            let span = list.span;
             for l in list.list.iter_mut() {
                 eval(l, env)?;
             }
             if list.list_type == ListType::Parens && !list.list.is_empty() {
-                let args = list
+                let args: Vec<Number> = list
                     .list
                     .drain(1..)
-                    .map(ast_to_f32)
+                    .map(|a| Number::from_ast(&a))
                     .collect::<Result<_, Error>>()?;
-                let first = if let Ast::Leaf(leaf) = &list.list[0] {
-                    if let AstLeaf::Symbol(sym) = leaf {
-                        sym
-                    } else {
-                        todo!()
+                let first = match &list.list[0] {
+                    Ast::Leaf(AstLeaf::Symbol(sym), _) => sym,
+                    head => {
+                        return Err(Error::EvalError(format!("`{}` is not a function", head))
+                            .at(head.span()))
                     }
-                } else {
-                    todo!()
                 };
-                if let Some(f) = env.0.get(first) {
-                    *ast = Ast::Leaf(AstLeaf::Float(f(args)))
+                if let Some((arity, f)) = env.0.get(first) {
+                    arity.check(first, args.len()).map_err(|e| e.at(span))?;
+                    *ast = f(args)?.to_ast().with_span(span)
                 } else {
-                    *ast = Ast::Leaf(AstLeaf::String(format!(r#""{}" not found"#, first)));
+                    return Err(
+                        Error::EvalError(format!("'{}' not found", first)).at(list.list[0].span())
+                    );
                 };
             }
             Ok(())
@@ -76,18 +60,17 @@ fn eval(ast: &mut Ast, env: &Env) -> Result<(), Error> {
 }
 
 fn print(ast: Ast) -> String {
-    format!("{}", ast)
+    ast.pr_str(true)
 }
 
-fn repl(s: String, env: &Env) -> String {
-    if let Ok(mut r) = read(s) {
-        match eval(&mut r, env) {
-            Ok(_) => print(r),
-            Err(e) => e.to_string(),
-        }
-    } else {
-        "unbalanced".to_owned()
+/// Reads, evaluates and prints every form in `s` in order, stopping at
+/// the first error.
+fn rep(s: &str, env: &Env) -> Result<(), Error> {
+    for mut ast in read(s)? {
+        eval(&mut ast, env)?;
+        println!("{}", print(ast));
     }
+    Ok(())
 }
 
 fn main() {
@@ -95,11 +78,15 @@ fn main() {
     if rl.load_history("history.txt").is_err() {
         println!("No previous history.");
     }
-    let mut hm: HashMap<String, Box<Fn(Vec<f32>) -> f32>> = HashMap::new();
-    hm.insert("+".to_owned(), Box::new(|args| args[0] + args[1]));
-    hm.insert("-".to_owned(), Box::new(|args| args[0] - args[1]));
-    hm.insert("*".to_owned(), Box::new(|args| args[0] * args[1]));
-    hm.insert("/".to_owned(), Box::new(|args| args[0] / args[1]));
+    let mut hm: HashMap<String, (Arity, Builtin)> = HashMap::new();
+    let variadic = |min, identity, op: fn(Number, Number) -> Result<Number, Error>| {
+        let f: Builtin = Box::new(move |args| numeric::fold(Number::Int(identity), &args, op));
+        (Arity::at_least(min), f)
+    };
+    hm.insert("+".to_owned(), variadic(0, 0, numeric::add));
+    hm.insert("-".to_owned(), variadic(1, 0, numeric::sub));
+    hm.insert("*".to_owned(), variadic(0, 1, numeric::mul));
+    hm.insert("/".to_owned(), variadic(1, 1, numeric::div));
 
     let env = Env(hm);
     loop {
@@ -107,7 +94,9 @@ fn main() {
         match readline {
             Ok(line) => {
                 rl.add_history_entry(&line);
-                println!("{}", repl(line, &env));
+                if let Err(e) = error::catch_panic(|| rep(&line, &env)) {
+                    println!("{}", diagnostic::render(&line, &e));
+                }
             }
             Err(ReadlineError::Interrupted) => break,
             Err(ReadlineError::Eof) => break,
@@ -1,31 +1,22 @@
+mod depth;
+mod diagnostic;
+mod error;
 mod formatter;
 mod lexer;
+mod numeric;
 mod reader;
 mod reader_macros;
+mod readline;
 
-use crate::reader::{Ast, AstLeaf, ListType};
-use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use crate::error::Error;
+use crate::numeric::Number;
+use crate::reader::{Arity, Ast, AstLeaf, LFunction, ListType};
+use crate::readline::Readline;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::mem;
 use std::rc::Rc;
-use thiserror::Error;
-
-#[derive(Error, Debug, PartialEq)]
-pub enum Error {
-    #[error("transition error `{0}`")]
-    TransitionError(String),
-    #[error("token termination error `{0}`")]
-    TokenTerminationError(String),
-    #[error("unbalanced parens")]
-    Unbalanced,
-    #[error("eof while parsing a string")]
-    EOF,
-    #[error("reader macro error")]
-    ReaderMacroError,
-    #[error("{0}")]
-    EvalError(String),
-}
 
 struct Env {
     env: HashMap<String, Ast>,
@@ -47,55 +38,79 @@ impl Env {
     }
 }
 
-fn read(s: String) -> Result<Ast, Error> {
-    reader::read(s)
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
 }
 
-fn ast_to_f32(ast: Ast) -> Result<f32, Error> {
-    if let Ast::Leaf(leaf) = ast {
-        return match leaf {
-            AstLeaf::Float(f) => Ok(f),
-            AstLeaf::Int(i) => Ok(i as f32),
-            _ => Err(Error::EvalError("cannot convert to f32".to_string())),
-        };
-    }
-    Err(Error::EvalError("not a leaf".to_string()))
+fn numbers(args: &[Ast]) -> Result<Vec<Number>, Error> {
+    args.iter().map(Number::from_ast).collect()
+}
+
+fn arithmetic(
+    args: Vec<Ast>,
+    identity: i64,
+    op: fn(Number, Number) -> Result<Number, Error>,
+) -> Result<Ast, Error> {
+    Ok(numeric::fold(Number::Int(identity), &numbers(&args)?, op)?.to_ast())
+}
+
+fn comparison(args: Vec<Ast>, test: fn(Ordering) -> bool) -> Result<Ast, Error> {
+    Ok(Ast::bool(numeric::compare_chain(&numbers(&args)?, test)?))
 }
 
 fn function_call(
+    name: &str,
     mut args: Vec<Ast>,
     env: Rc<RefCell<Env>>,
-    f: Rc<dyn Fn(Vec<Ast>) -> Result<Ast, Error>>,
+    f: &LFunction,
 ) -> Result<Ast, Error> {
+    f.arity.check(name, args.len())?;
     for l in args.iter_mut() {
         eval(l, env.clone())?;
     }
-    f(args)
+    (f.f)(args)
 }
 
 fn eval(ast: &mut Ast, env: Rc<RefCell<Env>>) -> Result<(), Error> {
+    let span = ast.span();
+    eval_form(ast, env).map_err(|e| e.at(span))
+}
+
+fn eval_form(ast: &mut Ast, env: Rc<RefCell<Env>>) -> Result<(), Error> {
     match ast {
-        Ast::Leaf(leaf) => {
+        Ast::Leaf(leaf, span) => {
             // resolve symbols
             match leaf {
                 AstLeaf::Symbol(sym) => {
-                    *ast = env.borrow().search(sym)?;
+                    let span = *span;
+                    *ast = env.borrow().search(sym)?.with_span(span);
                 }
                 _ => {}
             };
             Ok(())
         }
         Ast::List(list) => {
+            let list = Rc::make_mut(list);
+            let span = list.span;
             if list.list_type == ListType::Parens && !list.list.is_empty() {
                 let mut args: Vec<Ast> = list.list.drain(1..).collect();
                 match list.list[0].get_leaf()? {
                     AstLeaf::Symbol(s) => {
                         if s == "let*" {
+                            Arity::exactly(2).check(s, args.len())?;
                             let inner_env = Rc::new(RefCell::new(Env {
                                 env: HashMap::new(),
                                 parent: Some(env),
                             }));
-                            for bind in args[0].get_any_list_mut()?.chunks_mut(2) {
+                            let bindings_span = args[0].span();
+                            let bindings = args[0].get_any_list_mut()?;
+                            if bindings.len() % 2 != 0 {
+                                return Err(Error::EvalError(
+                                    "`let*` expects an even number of binding forms".to_owned(),
+                                )
+                                .at(bindings_span));
+                            }
+                            for bind in bindings.chunks_mut(2) {
                                 eval(&mut bind[1], inner_env.clone())?;
                                 inner_env
                                     .borrow_mut()
@@ -103,29 +118,27 @@ fn eval(ast: &mut Ast, env: Rc<RefCell<Env>>) -> Result<(), Error> {
                                     .insert(bind[0].get_symbol()?, bind[1].clone());
                             }
 
-                            let mut inner_args: Ast =
-                                args.drain(1..).collect::<Vec<Ast>>()[0].clone();
-                            *ast = inner_args;
+                            *ast = mem::take(&mut args[1]);
                             eval(ast, inner_env)?;
                         } else if s == "def!" {
+                            Arity::exactly(2).check(s, args.len())?;
                             eval(&mut args[1], env.clone())?;
                             env.borrow_mut()
                                 .env
                                 .insert(args[0].get_symbol()?, args[1].clone());
                             *ast = args[1].clone();
                         } else {
-                            let f_ast = env.borrow().search(s)?;
-                            let f = f_ast.get_function()?;
-                            *ast = function_call(args, env, f.clone())?;
+                            let head_span = list.list[0].span();
+                            let f_ast = env.borrow().search(s).map_err(|e| e.at(head_span))?;
+                            let f = f_ast.get_function().map_err(|e| e.at(head_span))?;
+                            *ast = function_call(s, args, env, f)?.with_span(span);
                         }
                     }
                     AstLeaf::Function(f) => {
-                        *ast = function_call(args, env, f.f.clone())?;
+                        *ast = function_call("function", args, env, f)?.with_span(span);
                     }
                     _ => return Err(Error::EvalError("not a function".to_owned())),
                 };
-            // let f = list.list[0].get_function()?;
-            // *ast = f(args)?;
             } else {
                 for l in list.list.iter_mut() {
                     eval(l, env.clone())?;
@@ -137,82 +150,81 @@ fn eval(ast: &mut Ast, env: Rc<RefCell<Env>>) -> Result<(), Error> {
 }
 
 fn print(ast: Ast) -> String {
-    format!("{}", ast)
+    ast.pr_str(true)
 }
 
-fn repl(s: String, env: Rc<RefCell<Env>>) -> String {
-    if let Ok(mut r) = read(s) {
-        match eval(&mut r, env) {
-            Ok(_) => print(r),
-            Err(e) => e.to_string(),
-        }
-    } else {
-        "unbalanced".to_owned()
+/// Reads, evaluates and prints every form in `s` in order, stopping at
+/// the first error.
+fn rep(s: &str, env: Rc<RefCell<Env>>) -> Result<(), Error> {
+    for mut ast in read(s)? {
+        eval(&mut ast, env.clone())?;
+        println!("{}", print(ast));
     }
+    Ok(())
 }
 
 fn main() {
-    let mut rl = Editor::<()>::new();
-    if rl.load_history("history.txt").is_err() {
-        println!("No previous history.");
-    }
+    let mut rl = Readline::new();
     let mut hm: HashMap<String, Ast> = HashMap::new();
     hm.insert(
         "+".to_owned(),
-        Ast::function(|args| {
-            Ok(Ast::Leaf(AstLeaf::Float(
-                ast_to_f32(args[0].clone())? + ast_to_f32(args[1].clone())?,
-            )))
-        }),
+        Ast::function(Arity::at_least(0), |args| arithmetic(args, 0, numeric::add)),
     );
     hm.insert(
         "-".to_owned(),
-        Ast::function(|args| {
-            Ok(Ast::Leaf(AstLeaf::Float(
-                ast_to_f32(args[0].clone())? - ast_to_f32(args[1].clone())?,
-            )))
-        }),
+        Ast::function(Arity::at_least(1), |args| arithmetic(args, 0, numeric::sub)),
     );
     hm.insert(
         "*".to_owned(),
-        Ast::function(|args| {
-            Ok(Ast::Leaf(AstLeaf::Float(
-                ast_to_f32(args[0].clone())? * ast_to_f32(args[1].clone())?,
-            )))
-        }),
+        Ast::function(Arity::at_least(0), |args| arithmetic(args, 1, numeric::mul)),
     );
     hm.insert(
         "/".to_owned(),
-        Ast::function(|args| {
-            Ok(Ast::Leaf(AstLeaf::Float(
-                ast_to_f32(args[0].clone())? / ast_to_f32(args[1].clone())?,
-            )))
+        Ast::function(Arity::at_least(1), |args| arithmetic(args, 1, numeric::div)),
+    );
+    hm.insert(
+        "mod".to_owned(),
+        Ast::function(Arity::exactly(2), |args| {
+            arithmetic(args, 0, numeric::modulo)
+        }),
+    );
+    hm.insert(
+        "rem".to_owned(),
+        Ast::function(Arity::exactly(2), |args| arithmetic(args, 0, numeric::rem)),
+    );
+    hm.insert(
+        "<".to_owned(),
+        Ast::function(Arity::at_least(1), |args| {
+            comparison(args, |o| o == Ordering::Less)
+        }),
+    );
+    hm.insert(
+        "<=".to_owned(),
+        Ast::function(Arity::at_least(1), |args| {
+            comparison(args, |o| o != Ordering::Greater)
+        }),
+    );
+    hm.insert(
+        ">".to_owned(),
+        Ast::function(Arity::at_least(1), |args| {
+            comparison(args, |o| o == Ordering::Greater)
+        }),
+    );
+    hm.insert(
+        ">=".to_owned(),
+        Ast::function(Arity::at_least(1), |args| {
+            comparison(args, |o| o != Ordering::Less)
         }),
     );
-
-    // hm.insert("-".to_owned(), Box::new(|args| args[0] - args[1]));
-    // hm.insert("*".to_owned(), Box::new(|args| args[0] * args[1]));
-    // hm.insert("/".to_owned(), Box::new(|args| args[0] / args[1]));
 
     let env = Rc::new(RefCell::new(Env {
         env: hm,
         parent: None,
     }));
-    loop {
-        let readline = rl.readline("user> ");
-        match readline {
-            Ok(line) => {
-                rl.add_history_entry(&line);
-                println!("{}", repl(line, env.clone()));
-            }
-            Err(ReadlineError::Interrupted) => break,
-            Err(ReadlineError::Eof) => break,
-
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break;
-            }
+    while let Some(input) = rl.read_input() {
+        if let Err(e) = error::catch_panic(|| rep(&input, env.clone())) {
+            println!("{}", diagnostic::render(&input, &e));
         }
     }
-    rl.save_history("history.txt").unwrap();
+    rl.save_history();
 }
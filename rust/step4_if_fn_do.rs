@@ -0,0 +1,175 @@
+mod core;
+mod depth;
+mod diagnostic;
+mod env;
+mod error;
+mod formatter;
+mod lexer;
+mod numeric;
+mod reader;
+mod reader_macros;
+mod readline;
+mod value;
+
+use crate::env::Env;
+use crate::error::Error;
+use crate::reader::{Arity, Ast, AstLeaf, ListType};
+use crate::readline::Readline;
+use crate::value::{Closure, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
+}
+
+/// Builds the function of `(fn* params body)`, closing over `env`. A
+/// parameter after `&` collects the remaining arguments into a list.
+fn closure(params: &Ast, body: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let mut names = params
+        .get_any_list()
+        .map_err(|e| e.at(params.span()))?
+        .iter()
+        .map(|p| p.get_symbol().map_err(|e| e.at(p.span())))
+        .collect::<Result<Vec<String>, Error>>()?;
+    let rest = match names.iter().position(|n| n == "&") {
+        Some(i) if i + 2 == names.len() => {
+            let rest = names.pop();
+            names.pop();
+            rest
+        }
+        Some(_) => {
+            return Err(Error::EvalError(
+                "`&` must be followed by exactly one parameter".to_owned(),
+            )
+            .at(params.span()))
+        }
+        None => None,
+    };
+    Ok(Value::Closure(Rc::new(Closure {
+        params: names,
+        rest,
+        body: body.clone(),
+        env: env.clone(),
+        eval,
+        is_macro: false,
+        meta: None,
+    })))
+}
+
+fn eval_all(forms: &[Ast], env: &Rc<RefCell<Env>>) -> Result<Vec<Value>, Error> {
+    forms.iter().map(|form| eval(form, env)).collect()
+}
+
+fn eval(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    eval_form(ast, env).map_err(|e| e.at(ast.span()))
+}
+
+fn eval_form(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let list = match ast {
+        Ast::Leaf(AstLeaf::Symbol(sym), _) => return env.borrow().search(sym),
+        Ast::Leaf(leaf, _) => return Value::from_leaf(leaf),
+        Ast::List(list) => list,
+    };
+    let (head, args) = match list.list_type {
+        ListType::Brackets => return Ok(Value::vector(eval_all(&list.list, env)?)),
+        ListType::Braces => return Ok(Value::map(eval_all(&list.list, env)?)),
+        ListType::Set => return Ok(Value::set(eval_all(&list.list, env)?)),
+        ListType::Parens => match list.list.split_first() {
+            Some(call) => call,
+            None => return Ok(Value::list(Vec::new())),
+        },
+    };
+    match head.get_symbol().ok().as_deref() {
+        Some("let*") => {
+            Arity::exactly(2).check("let*", args.len())?;
+            let bindings = args[0].get_any_list()?;
+            if bindings.len() % 2 != 0 {
+                return Err(Error::EvalError(
+                    "`let*` expects an even number of binding forms".to_owned(),
+                )
+                .at(args[0].span()));
+            }
+            let inner_env = Env::new(Some(env.clone()));
+            for bind in bindings.chunks(2) {
+                let name = bind[0].get_symbol()?;
+                let value = eval(&bind[1], &inner_env)?;
+                inner_env.borrow_mut().set(name, value);
+            }
+            eval(&args[1], &inner_env)
+        }
+        Some("def!") => {
+            Arity::exactly(2).check("def!", args.len())?;
+            let name = args[0].get_symbol()?;
+            let value = eval(&args[1], env)?;
+            env.borrow_mut().set(name, value.clone());
+            Ok(value)
+        }
+        Some("do") => {
+            let mut result = Value::Nil;
+            for form in args {
+                result = eval(form, env)?;
+            }
+            Ok(result)
+        }
+        Some("if") => {
+            Arity::between(2, 3).check("if", args.len())?;
+            let branch = if eval(&args[0], env)?.is_truthy() {
+                1
+            } else {
+                2
+            };
+            match args.get(branch) {
+                Some(form) => eval(form, env),
+                None => Ok(Value::Nil),
+            }
+        }
+        Some("fn*") => {
+            Arity::exactly(2).check("fn*", args.len())?;
+            closure(&args[0], &args[1], env)
+        }
+        name => {
+            let name = name.unwrap_or("function");
+            let f = eval(head, env)?;
+            let evaluated_args = |arity: Arity| {
+                arity.check(name, args.len())?;
+                eval_all(args, env)
+            };
+            match &f {
+                Value::Builtin(b) => (b.f)(evaluated_args(b.arity)?),
+                Value::Closure(c) => {
+                    eval(&c.body, &c.bind(evaluated_args(c.arity())?)).map_err(Error::unlocated)
+                }
+                _ => Err(Error::EvalError("not a function".to_owned()).at(head.span())),
+            }
+        }
+    }
+}
+
+fn print(value: Value) -> String {
+    value.pr_str(true)
+}
+
+/// Reads, evaluates and prints every form in `s` in order, stopping at
+/// the first error.
+fn rep(s: &str, env: &Rc<RefCell<Env>>) -> Result<(), Error> {
+    for ast in read(s)? {
+        println!("{}", print(eval(&ast, env)?));
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut rl = Readline::new();
+    let env = Env::new(None);
+    for (name, f) in core::ns() {
+        env.borrow_mut().set(name.to_owned(), f);
+    }
+
+    while let Some(input) = rl.read_input() {
+        if let Err(e) = error::catch_panic(|| rep(&input, &env)) {
+            println!("{}", diagnostic::render(&input, &e));
+        }
+    }
+    rl.save_history();
+}
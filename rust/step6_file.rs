@@ -0,0 +1,289 @@
+mod core;
+mod depth;
+mod diagnostic;
+mod env;
+mod error;
+mod formatter;
+mod lexer;
+mod numeric;
+mod reader;
+mod reader_macros;
+mod readline;
+mod value;
+
+use crate::env::Env;
+use crate::error::Error;
+use crate::lexer::Span;
+use crate::reader::{Arity, Ast, AstLeaf, ListType};
+use crate::readline::Readline;
+use crate::value::{Closure, Value};
+use std::cell::RefCell;
+
+use std::fs;
+use std::process;
+use std::rc::Rc;
+
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
+}
+
+/// Builds the function of `(fn* params body)`, closing over `env`. A
+/// parameter after `&` collects the remaining arguments into a list.
+fn closure(params: &Ast, body: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let mut names = params
+        .get_any_list()
+        .map_err(|e| e.at(params.span()))?
+        .iter()
+        .map(|p| p.get_symbol().map_err(|e| e.at(p.span())))
+        .collect::<Result<Vec<String>, Error>>()?;
+    let rest = match names.iter().position(|n| n == "&") {
+        Some(i) if i + 2 == names.len() => {
+            let rest = names.pop();
+            names.pop();
+            rest
+        }
+        Some(_) => {
+            return Err(Error::EvalError(
+                "`&` must be followed by exactly one parameter".to_owned(),
+            )
+            .at(params.span()))
+        }
+        None => None,
+    };
+    Ok(Value::Closure(Rc::new(Closure {
+        params: names,
+        rest,
+        body: body.clone(),
+        env: env.clone(),
+        eval,
+        is_macro: false,
+        meta: None,
+    })))
+}
+
+fn eval_all(forms: &[Ast], env: &Rc<RefCell<Env>>) -> Result<Vec<Value>, Error> {
+    forms.iter().map(|form| eval(form, env)).collect()
+}
+
+/// What is left to do after one step of evaluation: either the form's
+/// value is known, or it is the value of a form in tail position.
+enum Tail {
+    Return(Value),
+    Eval(Ast, Rc<RefCell<Env>>),
+    /// The body of a called closure, whose errors are reported at the call.
+    Call(Ast, Rc<RefCell<Env>>),
+}
+
+/// Evaluates `ast` as a trampoline: forms in tail position of `if`, `do`,
+/// `let*` and of a called closure's body replace the current form instead
+/// of recursing, so tail calls run in constant native stack.
+fn eval(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let _depth = depth::enter().map_err(|e| e.at(ast.span()))?;
+    let mut ast = ast.clone();
+    let mut env = env.clone();
+    let mut call = None;
+    loop {
+        let span = ast.span();
+        let located = |e: Error| match call {
+            Some(call) => e.unlocated().at(call),
+            None => e.at(span),
+        };
+        match eval_form(&ast, &env).map_err(located)? {
+            Tail::Return(value) => return Ok(value),
+            Tail::Eval(next_ast, next_env) => {
+                ast = next_ast;
+                env = next_env;
+            }
+            Tail::Call(body, body_env) => {
+                call = call.or(Some(span));
+                ast = body;
+                env = body_env;
+            }
+        }
+    }
+}
+
+fn eval_form(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Tail, Error> {
+    let list = match ast {
+        Ast::Leaf(AstLeaf::Symbol(sym), _) => return Ok(Tail::Return(env.borrow().search(sym)?)),
+        Ast::Leaf(leaf, _) => return Ok(Tail::Return(Value::from_leaf(leaf)?)),
+        Ast::List(list) => list,
+    };
+    let (head, args) = match list.list_type {
+        ListType::Brackets => return Ok(Tail::Return(Value::vector(eval_all(&list.list, env)?))),
+        ListType::Braces => return Ok(Tail::Return(Value::map(eval_all(&list.list, env)?))),
+        ListType::Set => return Ok(Tail::Return(Value::set(eval_all(&list.list, env)?))),
+        ListType::Parens => match list.list.split_first() {
+            Some(call) => call,
+            None => return Ok(Tail::Return(Value::list(Vec::new()))),
+        },
+    };
+    match head.get_symbol().ok().as_deref() {
+        Some("let*") => {
+            Arity::exactly(2).check("let*", args.len())?;
+            let bindings = args[0].get_any_list()?;
+            if bindings.len() % 2 != 0 {
+                return Err(Error::EvalError(
+                    "`let*` expects an even number of binding forms".to_owned(),
+                )
+                .at(args[0].span()));
+            }
+            let inner_env = Env::new(Some(env.clone()));
+            for bind in bindings.chunks(2) {
+                let name = bind[0].get_symbol()?;
+                let value = eval(&bind[1], &inner_env)?;
+                inner_env.borrow_mut().set(name, value);
+            }
+            Ok(Tail::Eval(args[1].clone(), inner_env))
+        }
+        Some("def!") => {
+            Arity::exactly(2).check("def!", args.len())?;
+            let name = args[0].get_symbol()?;
+            let value = eval(&args[1], env)?;
+            env.borrow_mut().set(name, value.clone());
+            Ok(Tail::Return(value))
+        }
+        Some("do") => match args.split_last() {
+            Some((last, init)) => {
+                eval_all(init, env)?;
+                Ok(Tail::Eval(last.clone(), env.clone()))
+            }
+            None => Ok(Tail::Return(Value::Nil)),
+        },
+        Some("if") => {
+            Arity::between(2, 3).check("if", args.len())?;
+            let branch = if eval(&args[0], env)?.is_truthy() {
+                1
+            } else {
+                2
+            };
+            match args.get(branch) {
+                Some(form) => Ok(Tail::Eval(form.clone(), env.clone())),
+                None => Ok(Tail::Return(Value::Nil)),
+            }
+        }
+        Some("fn*") => {
+            Arity::exactly(2).check("fn*", args.len())?;
+            Ok(Tail::Return(closure(&args[0], &args[1], env)?))
+        }
+        name => {
+            let name = name.unwrap_or("function");
+            let f = eval(head, env)?;
+            let evaluated_args = |arity: Arity| {
+                arity.check(name, args.len())?;
+                eval_all(args, env)
+            };
+            match &f {
+                Value::Builtin(b) => Ok(Tail::Return((b.f)(evaluated_args(b.arity)?)?)),
+                Value::Closure(c) => Ok(Tail::Call(
+                    c.body.clone(),
+                    c.bind(evaluated_args(c.arity())?),
+                )),
+                _ => Err(Error::EvalError("not a function".to_owned()).at(head.span())),
+            }
+        }
+    }
+}
+
+fn print(value: Value) -> String {
+    value.pr_str(true)
+}
+
+/// Reads, evaluates and prints every form in `s` in order, stopping at
+/// the first error.
+fn rep(s: &str, env: &Rc<RefCell<Env>>) -> Result<(), Error> {
+    for ast in read(s)? {
+        println!("{}", print(eval(&ast, env)?));
+    }
+    Ok(())
+}
+
+/// Evaluates every form in the file at `path`, returning the value of the
+/// last one. Errors are reported against the file rather than the input
+/// that loaded it.
+fn load_file(path: &str, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::EvalError(format!("could not read `{}`: {}", path, e)))?;
+    let run = || {
+        let mut result = Value::Nil;
+        for ast in read(&text)? {
+            result = eval(&ast, env)?;
+        }
+        Ok(result)
+    };
+    run().map_err(|e: Error| e.in_file(path, &text))
+}
+
+/// The global environment: the core functions, plus `eval` and `load-file`
+/// which work in it, and the script's arguments as `*ARGV*`.
+fn repl_env(argv: Vec<String>) -> Rc<RefCell<Env>> {
+    let env = Env::new(None);
+    for (name, f) in core::ns() {
+        env.borrow_mut().set(name.to_owned(), f);
+    }
+    let eval_env = env.clone();
+    env.borrow_mut().set(
+        "eval".to_owned(),
+        Value::builtin(Arity::exactly(1), move |args| {
+            eval(&args[0].to_ast(Span::default())?, &eval_env)
+        }),
+    );
+    let load_env = env.clone();
+    env.borrow_mut().set(
+        "load-file".to_owned(),
+        Value::builtin(Arity::exactly(1), move |args| match &args[0] {
+            Value::String(path) => load_file(path, &load_env),
+            other => Err(other.type_error("load-file", "a string")),
+        }),
+    );
+    env.borrow_mut().set(
+        "*ARGV*".to_owned(),
+        Value::list(argv.into_iter().map(Value::String).collect()),
+    );
+    env
+}
+
+fn repl(env: &Rc<RefCell<Env>>) {
+    let mut rl = Readline::new();
+    while let Some(input) = rl.read_input() {
+        if let Err(e) = error::catch_panic(|| rep(&input, env)) {
+            println!("{}", diagnostic::render(&input, &e));
+        }
+    }
+    rl.save_history();
+}
+
+/// `mal script.mal args...` runs the script with `args` as `*ARGV*`; with
+/// no arguments the interpreter is interactive. Returns the exit status.
+fn run() -> i32 {
+    let mut args = std::env::args().skip(1);
+    let script = args.next();
+    let env = repl_env(args.collect());
+    match script {
+        Some(path) => match error::catch_panic(|| load_file(&path, &env)) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}", diagnostic::render("", &e));
+                1
+            }
+        },
+        None => {
+            repl(&env);
+            0
+        }
+    }
+}
+
+fn main() {
+    let limit = depth::configured_limit();
+    match depth::run_with_limit(limit, run) {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!(
+                "Could not reserve a stack for {} levels of nesting: {}",
+                limit, e
+            );
+            process::exit(1);
+        }
+    }
+}
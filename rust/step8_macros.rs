@@ -0,0 +1,490 @@
+mod core;
+mod depth;
+mod diagnostic;
+mod env;
+mod error;
+mod formatter;
+mod lexer;
+mod numeric;
+mod reader;
+mod reader_macros;
+mod readline;
+mod value;
+
+use crate::env::Env;
+use crate::error::Error;
+use crate::lexer::Span;
+use crate::reader::{Arity, Ast, AstLeaf, ListType};
+use crate::readline::Readline;
+use crate::value::{Closure, Value};
+use std::cell::RefCell;
+
+use std::fs;
+use std::process;
+use std::rc::Rc;
+
+fn read(s: &str) -> Result<Vec<Ast>, Error> {
+    reader::read_all(s)
+}
+
+/// Builds the function of `(fn* params body)`, closing over `env`. A
+/// parameter after `&` collects the remaining arguments into a list.
+fn closure(params: &Ast, body: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let mut names = params
+        .get_any_list()
+        .map_err(|e| e.at(params.span()))?
+        .iter()
+        .map(|p| p.get_symbol().map_err(|e| e.at(p.span())))
+        .collect::<Result<Vec<String>, Error>>()?;
+    let rest = match names.iter().position(|n| n == "&") {
+        Some(i) if i + 2 == names.len() => {
+            let rest = names.pop();
+            names.pop();
+            rest
+        }
+        Some(_) => {
+            return Err(Error::EvalError(
+                "`&` must be followed by exactly one parameter".to_owned(),
+            )
+            .at(params.span()))
+        }
+        None => None,
+    };
+    Ok(Value::Closure(Rc::new(Closure {
+        params: names,
+        rest,
+        body: body.clone(),
+        env: env.clone(),
+        eval,
+        is_macro: false,
+        meta: None,
+    })))
+}
+
+/// If `ast` is the form `(name x)`, returns `x`.
+fn special_form<'a>(name: &str, ast: &'a Ast) -> Result<Option<&'a Ast>, Error> {
+    match ast {
+        Ast::List(l) if l.list_type == ListType::Parens => match l.list.split_first() {
+            Some((head, args)) if head.get_symbol().ok().as_deref() == Some(name) => {
+                Arity::exactly(1)
+                    .check(name, args.len())
+                    .map_err(|e| e.at(ast.span()))?;
+                Ok(Some(&args[0]))
+            }
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Expands the body of a `quasiquote` into code that builds it with
+/// `cons`, `concat` and `vec`, leaving the unquoted parts to be evaluated.
+/// `depth` counts the quasiquotes nested inside the one being expanded:
+/// unquotes inside them belong to them and are kept as they are.
+fn quasiquote(ast: &Ast, depth: usize) -> Result<Ast, Error> {
+    let _depth = depth::enter().map_err(|e| e.at(ast.span()))?;
+    let span = ast.span();
+    let call = |name: &str, args: Vec<Ast>| {
+        let mut forms = vec![Ast::symbol(name.to_owned()).with_span(span)];
+        forms.extend(args);
+        Ast::parens(forms).with_span(span)
+    };
+    let list = match ast {
+        Ast::List(l) if matches!(l.list_type, ListType::Parens | ListType::Brackets) => l,
+        Ast::Leaf(AstLeaf::Symbol(_), _) | Ast::List(_) => {
+            return Ok(call("quote", vec![ast.clone()]))
+        }
+        Ast::Leaf(..) => return Ok(ast.clone()),
+    };
+    if depth == 0 {
+        if let Some(form) = special_form("unquote", ast)? {
+            return Ok(form.clone());
+        }
+    }
+    let inner_depth = match list.list.first().map(Ast::get_symbol) {
+        Some(Ok(s)) if list.list_type == ListType::Parens => match s.as_str() {
+            "quasiquote" => depth + 1,
+            "unquote" | "splice-unquote" => depth.saturating_sub(1),
+            _ => depth,
+        },
+        _ => depth,
+    };
+    let mut result = Ast::parens(Vec::new()).with_span(span);
+    for element in list.list.iter().rev() {
+        result = match special_form("splice-unquote", element)? {
+            Some(spliced) if inner_depth == 0 => call("concat", vec![spliced.clone(), result]),
+            _ => call("cons", vec![quasiquote(element, inner_depth)?, result]),
+        };
+    }
+    Ok(match list.list_type {
+        ListType::Brackets => call("vec", vec![result]),
+        _ => result,
+    })
+}
+
+/// If `ast` calls a macro, returns the macro and its unevaluated arguments.
+fn macro_call<'a>(ast: &'a Ast, env: &Rc<RefCell<Env>>) -> Option<(Rc<Closure>, &'a [Ast])> {
+    let list = match ast {
+        Ast::List(l) if l.list_type == ListType::Parens => l,
+        _ => return None,
+    };
+    let (head, args) = list.list.split_first()?;
+    match &env.borrow().search(&head.get_symbol().ok()?) {
+        Ok(Value::Closure(c)) if c.is_macro => Some((c.clone(), args)),
+        _ => None,
+    }
+}
+
+/// Expands `ast` once if it calls a macro. The expansion takes the span of
+/// the call, so errors in generated code point at the macro's use.
+fn macroexpand_1(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Option<Ast>, Error> {
+    let (m, args) = match macro_call(ast, env) {
+        Some(call) => call,
+        None => return Ok(None),
+    };
+    let args = args
+        .iter()
+        .map(Value::from_ast)
+        .collect::<Result<Vec<Value>, Error>>()?;
+    m.arity().check("macro", args.len())?;
+    let expansion = (m.eval)(&m.body, &m.bind(args)).map_err(Error::unlocated)?;
+    Ok(Some(expansion.to_ast(ast.span())?))
+}
+
+/// Expands `ast` until it no longer calls a macro.
+fn macroexpand(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Ast, Error> {
+    let mut ast = ast.clone();
+    while let Some(expansion) = macroexpand_1(&ast, env)? {
+        ast = expansion;
+    }
+    Ok(ast)
+}
+
+fn eval_all(forms: &[Ast], env: &Rc<RefCell<Env>>) -> Result<Vec<Value>, Error> {
+    forms.iter().map(|form| eval(form, env)).collect()
+}
+
+/// What is left to do after one step of evaluation: either the form's
+/// value is known, or it is the value of a form in tail position.
+enum Tail {
+    Return(Value),
+    Eval(Ast, Rc<RefCell<Env>>),
+    /// The body of a called closure, whose errors are reported at the call.
+    Call(Ast, Rc<RefCell<Env>>),
+}
+
+/// Evaluates `ast` as a trampoline: forms in tail position of `if`, `do`,
+/// `let*` and of a called closure's body replace the current form instead
+/// of recursing, so tail calls run in constant native stack. A macro call
+/// is replaced by its expansion the same way.
+fn eval(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let _depth = depth::enter().map_err(|e| e.at(ast.span()))?;
+    let mut ast = ast.clone();
+    let mut env = env.clone();
+    let mut call = None;
+    loop {
+        let span = ast.span();
+        let located = |e: Error| match call {
+            Some(call) => e.unlocated().at(call),
+            None => e.at(span),
+        };
+        match eval_form(&ast, &env).map_err(located)? {
+            Tail::Return(value) => return Ok(value),
+            Tail::Eval(next_ast, next_env) => {
+                ast = next_ast;
+                env = next_env;
+            }
+            Tail::Call(body, body_env) => {
+                call = call.or(Some(span));
+                ast = body;
+                env = body_env;
+            }
+        }
+    }
+}
+
+fn eval_form(ast: &Ast, env: &Rc<RefCell<Env>>) -> Result<Tail, Error> {
+    if let Some(expansion) = macroexpand_1(ast, env)? {
+        return Ok(Tail::Eval(expansion, env.clone()));
+    }
+    let list = match ast {
+        Ast::Leaf(AstLeaf::Symbol(sym), _) => return Ok(Tail::Return(env.borrow().search(sym)?)),
+        Ast::Leaf(leaf, _) => return Ok(Tail::Return(Value::from_leaf(leaf)?)),
+        Ast::List(list) => list,
+    };
+    let (head, args) = match list.list_type {
+        ListType::Brackets => return Ok(Tail::Return(Value::vector(eval_all(&list.list, env)?))),
+        ListType::Braces => return Ok(Tail::Return(Value::map(eval_all(&list.list, env)?))),
+        ListType::Set => return Ok(Tail::Return(Value::set(eval_all(&list.list, env)?))),
+        ListType::Parens => match list.list.split_first() {
+            Some(call) => call,
+            None => return Ok(Tail::Return(Value::list(Vec::new()))),
+        },
+    };
+    match head.get_symbol().ok().as_deref() {
+        Some("let*") => {
+            Arity::exactly(2).check("let*", args.len())?;
+            let bindings = args[0].get_any_list()?;
+            if bindings.len() % 2 != 0 {
+                return Err(Error::EvalError(
+                    "`let*` expects an even number of binding forms".to_owned(),
+                )
+                .at(args[0].span()));
+            }
+            let inner_env = Env::new(Some(env.clone()));
+            for bind in bindings.chunks(2) {
+                let name = bind[0].get_symbol()?;
+                let value = eval(&bind[1], &inner_env)?;
+                inner_env.borrow_mut().set(name, value);
+            }
+            Ok(Tail::Eval(args[1].clone(), inner_env))
+        }
+        Some("def!") => {
+            Arity::exactly(2).check("def!", args.len())?;
+            let name = args[0].get_symbol()?;
+            let value = eval(&args[1], env)?;
+            env.borrow_mut().set(name, value.clone());
+            Ok(Tail::Return(value))
+        }
+        Some("defmacro!") => {
+            Arity::exactly(2).check("defmacro!", args.len())?;
+            let name = args[0].get_symbol()?;
+            let value = match &eval(&args[1], env)? {
+                Value::Closure(c) => Value::Closure(Rc::new(Closure {
+                    is_macro: true,
+                    ..(**c).clone()
+                })),
+                other => {
+                    return Err(other
+                        .type_error("defmacro!", "a function")
+                        .at(args[1].span()))
+                }
+            };
+            env.borrow_mut().set(name, value.clone());
+            Ok(Tail::Return(value))
+        }
+        Some("macroexpand") => {
+            Arity::exactly(1).check("macroexpand", args.len())?;
+            Ok(Tail::Return(Value::from_ast(&macroexpand(&args[0], env)?)?))
+        }
+        Some("macroexpand-1") => {
+            Arity::exactly(1).check("macroexpand-1", args.len())?;
+            let expansion = macroexpand_1(&args[0], env)?;
+            Ok(Tail::Return(Value::from_ast(
+                expansion.as_ref().unwrap_or(&args[0]),
+            )?))
+        }
+        Some("do") => match args.split_last() {
+            Some((last, init)) => {
+                eval_all(init, env)?;
+                Ok(Tail::Eval(last.clone(), env.clone()))
+            }
+            None => Ok(Tail::Return(Value::Nil)),
+        },
+        Some("if") => {
+            Arity::between(2, 3).check("if", args.len())?;
+            let branch = if eval(&args[0], env)?.is_truthy() {
+                1
+            } else {
+                2
+            };
+            match args.get(branch) {
+                Some(form) => Ok(Tail::Eval(form.clone(), env.clone())),
+                None => Ok(Tail::Return(Value::Nil)),
+            }
+        }
+        Some("quote") => {
+            Arity::exactly(1).check("quote", args.len())?;
+            Ok(Tail::Return(Value::from_ast(&args[0])?))
+        }
+        Some("quasiquoteexpand") => {
+            Arity::exactly(1).check("quasiquoteexpand", args.len())?;
+            Ok(Tail::Return(Value::from_ast(&quasiquote(&args[0], 0)?)?))
+        }
+        Some("quasiquote") => {
+            Arity::exactly(1).check("quasiquote", args.len())?;
+            Ok(Tail::Eval(quasiquote(&args[0], 0)?, env.clone()))
+        }
+        Some("fn*") => {
+            Arity::exactly(2).check("fn*", args.len())?;
+            Ok(Tail::Return(closure(&args[0], &args[1], env)?))
+        }
+        name => {
+            let name = name.unwrap_or("function");
+            let f = eval(head, env)?;
+            let evaluated_args = |arity: Arity| {
+                arity.check(name, args.len())?;
+                eval_all(args, env)
+            };
+            match &f {
+                Value::Builtin(b) => Ok(Tail::Return((b.f)(evaluated_args(b.arity)?)?)),
+                Value::Closure(c) => Ok(Tail::Call(
+                    c.body.clone(),
+                    c.bind(evaluated_args(c.arity())?),
+                )),
+                _ => Err(Error::EvalError("not a function".to_owned()).at(head.span())),
+            }
+        }
+    }
+}
+
+fn print(value: Value) -> String {
+    value.pr_str(true)
+}
+
+/// Reads, evaluates and prints every form in `s` in order, stopping at
+/// the first error.
+fn rep(s: &str, env: &Rc<RefCell<Env>>) -> Result<(), Error> {
+    for ast in read(s)? {
+        println!("{}", print(eval(&ast, env)?));
+    }
+    Ok(())
+}
+
+/// Macros defined in mal itself, available before `core.mal` is loaded.
+const PRELUDE: &str = r#"
+(defmacro! cond
+  (fn* (& xs)
+    (if (> (count xs) 0)
+      (list 'if (first xs)
+        (if (> (count xs) 1)
+          (nth xs 1)
+          (throw "odd number of forms to cond"))
+        (cons 'cond (rest (rest xs)))))))
+
+(defmacro! or
+  (fn* (& xs)
+    (if (empty? xs)
+      nil
+      (if (= 1 (count xs))
+        (first xs)
+        `(let* (or_FIXME ~(first xs))
+           (if or_FIXME or_FIXME (or ~@(rest xs))))))))
+"#;
+
+/// Evaluates every form in the file at `path`, returning the value of the
+/// last one. Errors are reported against the file rather than the input
+/// that loaded it.
+fn load_file(path: &str, env: &Rc<RefCell<Env>>) -> Result<Value, Error> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::EvalError(format!("could not read `{}`: {}", path, e)))?;
+    let run = || {
+        let mut result = Value::Nil;
+        for ast in read(&text)? {
+            result = eval(&ast, env)?;
+        }
+        Ok(result)
+    };
+    run().map_err(|e: Error| e.in_file(path, &text))
+}
+
+/// The global environment: the core functions, plus `eval` and `load-file`
+/// which work in it, the script's arguments as `*ARGV*` and the prelude's
+/// macros.
+fn repl_env(argv: Vec<String>) -> Result<Rc<RefCell<Env>>, Error> {
+    let env = Env::new(None);
+    for (name, f) in core::ns() {
+        env.borrow_mut().set(name.to_owned(), f);
+    }
+    let eval_env = env.clone();
+    env.borrow_mut().set(
+        "eval".to_owned(),
+        Value::builtin(Arity::exactly(1), move |args| {
+            eval(&args[0].to_ast(Span::default())?, &eval_env)
+        }),
+    );
+    let load_env = env.clone();
+    env.borrow_mut().set(
+        "load-file".to_owned(),
+        Value::builtin(Arity::exactly(1), move |args| match &args[0] {
+            Value::String(path) => load_file(path, &load_env),
+            other => Err(other.type_error("load-file", "a string")),
+        }),
+    );
+    env.borrow_mut().set(
+        "*ARGV*".to_owned(),
+        Value::list(argv.into_iter().map(Value::String).collect()),
+    );
+    for ast in read(PRELUDE)? {
+        eval(&ast, &env)?;
+    }
+    Ok(env)
+}
+
+fn repl(env: &Rc<RefCell<Env>>) {
+    let mut rl = Readline::new();
+    while let Some(input) = rl.read_input() {
+        if let Err(e) = error::catch_panic(|| rep(&input, env)) {
+            println!("{}", diagnostic::render(&input, &e));
+        }
+    }
+    rl.save_history();
+}
+
+/// `mal script.mal args...` runs the script with `args` as `*ARGV*`; with
+/// no arguments the interpreter is interactive. Returns the exit status.
+fn run() -> i32 {
+    let mut args = std::env::args().skip(1);
+    let script = args.next();
+    let env = match repl_env(args.collect()) {
+        Ok(env) => env,
+        Err(e) => {
+            eprintln!("{}", diagnostic::render(PRELUDE, &e));
+            return 1;
+        }
+    };
+    match script {
+        Some(path) => match error::catch_panic(|| load_file(&path, &env)) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("{}", diagnostic::render("", &e));
+                1
+            }
+        },
+        None => {
+            repl(&env);
+            0
+        }
+    }
+}
+
+fn main() {
+    let limit = depth::configured_limit();
+    match depth::run_with_limit(limit, run) {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!(
+                "Could not reserve a stack for {} levels of nesting: {}",
+                limit, e
+            );
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rep_last(s: &str) -> String {
+        let env = repl_env(vec![]).unwrap();
+        let mut result = Value::Nil;
+        for ast in read(s).unwrap() {
+            result = eval(&ast, &env).unwrap();
+        }
+        print(result)
+    }
+
+    #[test]
+    fn expansions_containing_functions() {
+        assert_eq!(rep_last("(defmacro! m (fn* [] (list + 1 2))) (m)"), "3");
+        assert_eq!(
+            rep_last("(def! a (atom 5)) (defmacro! m (fn* [] (list 'deref a))) (m)"),
+            "5"
+        );
+        assert_eq!(
+            rep_last("(defmacro! m (fn* [] (list + 1 2))) (macroexpand (m))"),
+            "(#<function> 1 2)"
+        );
+    }
+}
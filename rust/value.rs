@@ -0,0 +1,733 @@
+use crate::depth;
+use crate::env::Env;
+use crate::error::Error;
+use crate::formatter::{write_escaped, write_float, write_regex, write_tree, Elements};
+use crate::lexer::Span;
+use crate::numeric::Number;
+use crate::reader::{Arity, Ast, AstLeaf, Embed, Embedded, ListType, Pattern};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::iter;
+use std::mem;
+use std::rc::Rc;
+
+/// What evaluation produces. Unlike `Ast`, a value carries no source
+/// location, and collections share their elements so copies are cheap.
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Keyword(String),
+    Symbol(String),
+    Regex(Pattern),
+    List(Rc<Vec<Value>>, Meta),
+    Vector(Rc<Vec<Value>>, Meta),
+    Map(Rc<ValueMap>, Meta),
+    Set(Rc<ValueSet>, Meta),
+    Builtin(Builtin),
+    Closure(Rc<Closure>),
+    Atom(Rc<RefCell<Value>>),
+}
+
+/// The entries of a map. Hashing is deterministic, so a map prints its
+/// entries in the same order every time the program runs.
+pub(crate) type ValueMap = HashMap<Value, Value, BuildHasherDefault<DefaultHasher>>;
+
+/// The members of a set, hashed like the keys of a `ValueMap`.
+pub(crate) type ValueSet = HashSet<Value, BuildHasherDefault<DefaultHasher>>;
+
+/// Metadata attached by `with-meta`; it does not take part in equality or
+/// printing.
+pub(crate) type Meta = Option<Rc<Value>>;
+
+/// The evaluator a closure's body runs in, so that functions can be called
+/// from builtins which do not know about it.
+pub(crate) type EvalFn = fn(&Ast, &Rc<RefCell<Env>>) -> Result<Value, Error>;
+
+#[derive(Clone)]
+pub(crate) struct Builtin {
+    pub(crate) arity: Arity,
+    pub(crate) f: Rc<dyn Fn(Vec<Value>) -> Result<Value, Error>>,
+    pub(crate) meta: Meta,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("arity", &self.arity)
+            .field("f", &Rc::as_ptr(&self.f))
+            .field("meta", &self.meta)
+            .finish()
+    }
+}
+
+/// A function made by `fn*`. The body is shared with the form it was
+/// written in; calling the function only reads it.
+#[derive(Clone)]
+pub(crate) struct Closure {
+    pub(crate) params: Vec<String>,
+    /// The parameter after `&`, bound to a list of the remaining arguments.
+    pub(crate) rest: Option<String>,
+    pub(crate) body: Ast,
+    pub(crate) env: Rc<RefCell<Env>>,
+    pub(crate) eval: EvalFn,
+    /// Set by `defmacro!`: calls receive their arguments unevaluated and
+    /// return the code to evaluate in their place.
+    pub(crate) is_macro: bool,
+    pub(crate) meta: Meta,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("params", &self.params)
+            .field("rest", &self.rest)
+            .field("body", &self.body)
+            .field("is_macro", &self.is_macro)
+            .field("meta", &self.meta)
+            .finish()
+    }
+}
+
+impl Closure {
+    pub(crate) fn arity(&self) -> Arity {
+        match self.rest {
+            Some(_) => Arity::at_least(self.params.len()),
+            None => Arity::exactly(self.params.len()),
+        }
+    }
+
+    /// A fresh scope on top of the closure's environment with the
+    /// parameters bound to `args`, which must match `arity()`.
+    pub(crate) fn bind(&self, args: Vec<Value>) -> Rc<RefCell<Env>> {
+        let env = Env::new(Some(self.env.clone()));
+        let mut args = args.into_iter();
+        for (name, arg) in self.params.iter().zip(&mut args) {
+            env.borrow_mut().set(name.clone(), arg);
+        }
+        if let Some(rest) = &self.rest {
+            env.borrow_mut()
+                .set(rest.clone(), Value::list(args.collect()));
+        }
+        env
+    }
+}
+
+impl Value {
+    pub(crate) fn list(values: Vec<Value>) -> Self {
+        Value::List(Rc::new(values), None)
+    }
+    pub(crate) fn vector(values: Vec<Value>) -> Self {
+        Value::Vector(Rc::new(values), None)
+    }
+    /// The map of the alternating keys and values in `values`; a later key
+    /// replaces an equal earlier one.
+    pub(crate) fn map(values: Vec<Value>) -> Self {
+        #[allow(clippy::mutable_key_type)]
+        let mut entries = ValueMap::default();
+        let mut values = values.into_iter();
+        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+            entries.insert(key, value);
+        }
+        Value::Map(Rc::new(entries), None)
+    }
+    /// The set of `values`, each kept once.
+    pub(crate) fn set(values: Vec<Value>) -> Self {
+        Value::Set(Rc::new(values.into_iter().collect()), None)
+    }
+    pub(crate) fn atom(value: Value) -> Self {
+        Value::Atom(Rc::new(RefCell::new(value)))
+    }
+    pub(crate) fn builtin(
+        arity: Arity,
+        f: impl Fn(Vec<Value>) -> Result<Value, Error> + 'static,
+    ) -> Self {
+        Value::Builtin(Builtin {
+            arity,
+            f: Rc::new(f),
+            meta: None,
+        })
+    }
+
+    /// The value of a literal leaf. Symbols stand for themselves, as they
+    /// do under `quote`.
+    pub(crate) fn from_leaf(leaf: &AstLeaf) -> Result<Self, Error> {
+        Ok(match leaf {
+            AstLeaf::Symbol(s) => Value::Symbol(s.clone()),
+            AstLeaf::Int(i) => Value::Int(*i),
+            AstLeaf::Float(f) => Value::Float(*f),
+            AstLeaf::String(s) => Value::String(s.clone()),
+            AstLeaf::Keyword(k) => Value::Keyword(k.clone()),
+            AstLeaf::Bool(b) => Value::Bool(*b),
+            AstLeaf::Nil => Value::Nil,
+            AstLeaf::Regex(p) => Value::Regex(p.clone()),
+            AstLeaf::Embedded(e) => match e.downcast_ref::<Value>() {
+                Some(v) => v.clone(),
+                None => return Err(Error::Internal("foreign value in code".to_owned())),
+            },
+            AstLeaf::Function(_) => {
+                return Err(Error::Internal(
+                    "native function in a source form".to_owned(),
+                ))
+            }
+        })
+    }
+
+    /// The value of `ast` as data, as `quote` sees it.
+    pub(crate) fn from_ast(ast: &Ast) -> Result<Self, Error> {
+        let _depth = depth::enter()?;
+        match ast {
+            Ast::Leaf(leaf, _) => Value::from_leaf(leaf),
+            Ast::List(l) => {
+                let values = l
+                    .list
+                    .iter()
+                    .map(Value::from_ast)
+                    .collect::<Result<_, _>>()?;
+                Ok(match l.list_type {
+                    ListType::Parens => Value::list(values),
+                    ListType::Brackets => Value::vector(values),
+                    ListType::Braces => Value::map(values),
+                    ListType::Set => Value::set(values),
+                })
+            }
+        }
+    }
+
+    /// The form this value stands for when evaluated as code, as by `eval`.
+    /// Every node is attributed to `span`. Functions and atoms have no
+    /// written form, so they are embedded to evaluate to themselves.
+    pub(crate) fn to_ast(&self, span: Span) -> Result<Ast, Error> {
+        let _depth = depth::enter()?;
+        let list = |list_type, values: &[Value]| -> Result<Ast, Error> {
+            let forms = values
+                .iter()
+                .map(|v| v.to_ast(span))
+                .collect::<Result<_, _>>()?;
+            Ok(Ast::list(list_type, forms).with_span(span))
+        };
+        let leaf = match self {
+            Value::Nil => AstLeaf::Nil,
+            Value::Bool(b) => AstLeaf::Bool(*b),
+            Value::Int(i) => AstLeaf::Int(*i),
+            Value::Float(f) => AstLeaf::Float(*f),
+            Value::String(s) => AstLeaf::String(s.clone()),
+            Value::Keyword(k) => AstLeaf::Keyword(k.clone()),
+            Value::Symbol(s) => AstLeaf::Symbol(s.clone()),
+            Value::Regex(p) => AstLeaf::Regex(p.clone()),
+            Value::List(l, _) => return list(ListType::Parens, l),
+            Value::Vector(v, _) => return list(ListType::Brackets, v),
+            Value::Map(m, _) => {
+                let entries = m
+                    .iter()
+                    .flat_map(|(k, v)| iter::once(k).chain(iter::once(v)));
+                return list(ListType::Braces, &entries.cloned().collect::<Vec<_>>());
+            }
+            Value::Set(s, _) => return list(ListType::Set, &s.iter().cloned().collect::<Vec<_>>()),
+            Value::Builtin(_) | Value::Closure(_) | Value::Atom(_) => {
+                AstLeaf::Embedded(Embedded::new(self.clone()))
+            }
+        };
+        Ok(Ast::Leaf(leaf, span))
+    }
+
+    /// Calls the function with already evaluated arguments.
+    pub(crate) fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match self {
+            Value::Builtin(b) => {
+                b.arity.check("function", args.len())?;
+                (b.f)(args)
+            }
+            Value::Closure(c) => {
+                c.arity().check("function", args.len())?;
+                (c.eval)(&c.body, &c.bind(args)).map_err(Error::unlocated)
+            }
+            _ => Err(Error::EvalError(format!("`{}` is not a function", self))),
+        }
+    }
+
+    /// The error for builtin `name` getting this value where it needs
+    /// `expected`, such as "a string".
+    pub(crate) fn type_error(&self, name: &str, expected: &str) -> Error {
+        Error::EvalError(format!(
+            "`{}` expects {}, found {} {}",
+            name,
+            expected,
+            self.type_name(),
+            self
+        ))
+    }
+
+    /// Short human name of the kind of value this is, for type errors.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Keyword(_) => "keyword",
+            Value::Symbol(_) => "symbol",
+            Value::Regex(_) => "regex",
+            Value::List(..) => "list",
+            Value::Vector(..) => "vector",
+            Value::Map(..) => "map",
+            Value::Set(..) => "set",
+            Value::Builtin(_) | Value::Closure(_) => "function",
+            Value::Atom(_) => "atom",
+        }
+    }
+
+    /// Only `nil` and `false` are false in a condition.
+    pub(crate) fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub(crate) fn to_number(&self) -> Result<Number, Error> {
+        match self {
+            Value::Int(i) => Ok(Number::Int(*i)),
+            Value::Float(f) => Ok(Number::Float(*f)),
+            _ => Err(Error::EvalError(format!(
+                "expected a number, found {} {}",
+                self.type_name(),
+                self
+            ))),
+        }
+    }
+
+    /// The elements of a list or vector.
+    pub(crate) fn as_sequence(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(l, _) | Value::Vector(l, _) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// The metadata attached by `with-meta`, or `nil`.
+    pub(crate) fn meta(&self) -> Value {
+        let meta = match self {
+            Value::List(_, m) | Value::Vector(_, m) | Value::Map(_, m) | Value::Set(_, m) => m,
+            Value::Builtin(b) => &b.meta,
+            Value::Closure(c) => &c.meta,
+            _ => &None,
+        };
+        meta.as_deref().cloned().unwrap_or(Value::Nil)
+    }
+
+    /// A copy of this collection or function carrying `meta`; the original
+    /// keeps its own metadata.
+    pub(crate) fn with_meta(&self, meta: Value) -> Result<Value, Error> {
+        let meta = Some(Rc::new(meta));
+        Ok(match self {
+            Value::List(l, _) => Value::List(l.clone(), meta),
+            Value::Vector(v, _) => Value::Vector(v.clone(), meta),
+            Value::Map(m, _) => Value::Map(m.clone(), meta),
+            Value::Set(s, _) => Value::Set(s.clone(), meta),
+            Value::Builtin(b) => Value::Builtin(Builtin { meta, ..b.clone() }),
+            Value::Closure(c) => Value::Closure(Rc::new(Closure {
+                meta,
+                ..(**c).clone()
+            })),
+            _ => return Err(self.type_error("with-meta", "a collection or function")),
+        })
+    }
+
+    /// Prints the value; `readably` escapes strings the way `prn` does,
+    /// otherwise they are printed raw as by `println`.
+    pub(crate) fn pr_str(&self, readably: bool) -> String {
+        if readably {
+            format!("{}", self)
+        } else {
+            format!("{:#}", self)
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(n: Number) -> Self {
+        match n {
+            Number::Int(i) => Value::Int(i),
+            Number::Float(f) => Value::Float(f),
+        }
+    }
+}
+
+/// Structural equality, except that a list and a vector with equal
+/// elements are equal. Functions are only equal to themselves. Nested
+/// sequences and map values are compared with a worklist rather than by
+/// recursion, so arbitrarily deep values cannot overflow the stack.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let equal = match pair {
+                (Value::Nil, Value::Nil) => true,
+                (Value::Bool(a), Value::Bool(b)) => a == b,
+                (Value::Int(a), Value::Int(b)) => a == b,
+                (Value::Float(a), Value::Float(b)) => a == b,
+                (Value::String(a), Value::String(b)) => a == b,
+                (Value::Keyword(a), Value::Keyword(b)) => a == b,
+                (Value::Symbol(a), Value::Symbol(b)) => a == b,
+                (Value::Regex(a), Value::Regex(b)) => a == b,
+                (Value::Map(a, _), Value::Map(b, _)) => {
+                    a.len() == b.len()
+                        && a.iter().all(|(k, v)| match b.get(k) {
+                            Some(w) => {
+                                pending.push((v, w));
+                                true
+                            }
+                            None => false,
+                        })
+                }
+                (Value::Set(a, _), Value::Set(b, _)) => a == b,
+                (Value::Builtin(a), Value::Builtin(b)) => Rc::ptr_eq(&a.f, &b.f),
+                (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+                (Value::Atom(a), Value::Atom(b)) => Rc::ptr_eq(a, b),
+                (a, b) => match (a.as_sequence(), b.as_sequence()) {
+                    (Some(a), Some(b)) => {
+                        pending.extend(a.iter().zip(b));
+                        a.len() == b.len()
+                    }
+                    _ => false,
+                },
+            };
+            if !equal {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A step in hashing a value without recursion.
+enum HashStep<'a> {
+    Visit(&'a Value),
+    /// Replaces the last `count` hashes with one that depends on their
+    /// order, under `tag`.
+    Ordered(u8, usize),
+    /// Like `Ordered`, but independent of the order of the hashes.
+    Unordered(u8, usize),
+}
+
+// Maps can be keys too, so hashing follows `PartialEq`: lists and vectors
+// hash alike, the entries of maps and sets are combined independently of
+// their order, and functions and atoms hash by identity. Each element is
+// hashed on its own and the results combined, using a worklist like
+// equality does.
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut steps = vec![HashStep::Visit(self)];
+        let mut hashes: Vec<u64> = Vec::new();
+        while let Some(step) = steps.pop() {
+            let mut hasher = DefaultHasher::new();
+            match step {
+                HashStep::Visit(Value::List(l, _)) | HashStep::Visit(Value::Vector(l, _)) => {
+                    steps.push(HashStep::Ordered(7, l.len()));
+                    steps.extend(l.iter().rev().map(HashStep::Visit));
+                    continue;
+                }
+                HashStep::Visit(Value::Map(m, _)) => {
+                    steps.push(HashStep::Unordered(8, m.len()));
+                    for (k, v) in m.iter() {
+                        steps.push(HashStep::Ordered(14, 2));
+                        steps.push(HashStep::Visit(v));
+                        steps.push(HashStep::Visit(k));
+                    }
+                    continue;
+                }
+                HashStep::Visit(Value::Set(s, _)) => {
+                    steps.push(HashStep::Unordered(12, s.len()));
+                    steps.extend(s.iter().map(HashStep::Visit));
+                    continue;
+                }
+                HashStep::Visit(value) => match value {
+                    Value::Nil => 0.hash(&mut hasher),
+                    Value::Bool(b) => (1, b).hash(&mut hasher),
+                    Value::Int(i) => (2, i).hash(&mut hasher),
+                    // `0.0 == -0.0`, so both must hash the same.
+                    Value::Float(f) => {
+                        (3, if *f == 0.0 { 0 } else { f.to_bits() }).hash(&mut hasher)
+                    }
+                    Value::String(s) => (4, s).hash(&mut hasher),
+                    Value::Keyword(k) => (5, k).hash(&mut hasher),
+                    Value::Symbol(s) => (6, s).hash(&mut hasher),
+                    Value::Builtin(b) => {
+                        (9, Rc::as_ptr(&b.f) as *const () as usize).hash(&mut hasher)
+                    }
+                    Value::Closure(c) => (10, Rc::as_ptr(c) as usize).hash(&mut hasher),
+                    Value::Atom(a) => (11, Rc::as_ptr(a) as usize).hash(&mut hasher),
+                    Value::Regex(p) => (13, p.as_str()).hash(&mut hasher),
+                    Value::List(..) | Value::Vector(..) | Value::Map(..) | Value::Set(..) => {
+                        unreachable!("collections are visited above")
+                    }
+                },
+                HashStep::Ordered(tag, count) => {
+                    let items = hashes.split_off(hashes.len() - count);
+                    (tag, items).hash(&mut hasher);
+                }
+                HashStep::Unordered(tag, count) => {
+                    let items = hashes.split_off(hashes.len() - count);
+                    let sum = items.iter().fold(0u64, |sum, h| sum.wrapping_add(*h));
+                    (tag, count, sum).hash(&mut hasher);
+                }
+            }
+            hashes.push(hasher.finish());
+        }
+        state.write_u64(hashes[0]);
+    }
+}
+
+// Dropping a deeply nested value would otherwise recurse once per level.
+// Elements owned by nothing else are moved onto a worklist first, so each
+// one is dropped with its collections already emptied.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_elements(&mut pending);
+        while let Some(mut value) = pending.pop() {
+            value.take_elements(&mut pending);
+        }
+    }
+}
+
+impl Value {
+    /// Moves the elements of this collection or atom into `into`, if
+    /// nothing else shares them.
+    fn take_elements(&mut self, into: &mut Vec<Value>) {
+        match self {
+            Value::List(l, _) | Value::Vector(l, _) => {
+                if let Some(l) = Rc::get_mut(l) {
+                    into.append(l);
+                }
+            }
+            Value::Map(m, _) => {
+                if let Some(m) = Rc::get_mut(m) {
+                    for (k, v) in m.drain() {
+                        into.push(k);
+                        into.push(v);
+                    }
+                }
+            }
+            Value::Set(s, _) => {
+                if let Some(s) = Rc::get_mut(s) {
+                    into.extend(s.drain());
+                }
+            }
+            Value::Atom(a) => {
+                if let Some(a) = Rc::get_mut(a) {
+                    into.push(mem::replace(a.get_mut(), Value::Nil));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Equality is reflexive for every value except a NaN float, which is
+/// never found again once used as a map key.
+impl Eq for Value {}
+
+impl Embed for Value {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn equals(&self, other: &dyn Embed) -> bool {
+        other.as_any().downcast_ref() == Some(self)
+    }
+}
+
+// Printed the same way as the `Ast` it was read from, including the `{:#}`
+// flag for raw strings.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_tree(
+            f,
+            self,
+            |value| match value {
+                Value::List(l, _) => Some(("(", Box::new(l.iter()) as Elements<_>, ")")),
+                Value::Vector(v, _) => Some(("[", Box::new(v.iter()) as Elements<_>, "]")),
+                Value::Map(m, _) => Some((
+                    "{",
+                    Box::new(
+                        m.iter()
+                            .flat_map(|(k, v)| iter::once(k).chain(iter::once(v))),
+                    ),
+                    "}",
+                )),
+                Value::Set(s, _) => Some(("#{", Box::new(s.iter()) as Elements<_>, "}")),
+                _ => None,
+            },
+            |value, f| match value {
+                Value::Nil => write!(f, "nil"),
+                Value::Bool(b) => write!(f, "{}", b),
+                Value::Int(i) => write!(f, "{}", i),
+                Value::Float(x) => write_float(f, *x),
+                Value::String(s) if f.alternate() => write!(f, "{}", s),
+                Value::String(s) => write_escaped(f, s),
+                Value::Keyword(k) => write!(f, ":{}", k),
+                Value::Symbol(s) => write!(f, "{}", s),
+                Value::Regex(p) if f.alternate() => write!(f, "{}", p.as_str()),
+                Value::Regex(p) => write_regex(f, p.as_str()),
+                Value::Closure(c) if c.is_macro => write!(f, "#<macro>"),
+                Value::Builtin(_) | Value::Closure(_) => write!(f, "#<function>"),
+                Value::Atom(a) => {
+                    write!(f, "(atom ")?;
+                    fmt::Display::fmt(&*a.borrow(), f)?;
+                    write!(f, ")")
+                }
+                Value::List(..) | Value::Vector(..) | Value::Map(..) | Value::Set(..) => Ok(()),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequential_equality() {
+        let list = Value::list(vec![Value::Int(1), Value::vector(vec![Value::Int(2)])]);
+        let vector = Value::vector(vec![Value::Int(1), Value::list(vec![Value::Int(2)])]);
+        assert_eq!(list, vector);
+        assert_ne!(list, Value::vector(vec![Value::Int(1)]));
+        assert_ne!(Value::vector(vec![]), Value::Nil);
+        assert_ne!(
+            Value::Keyword("a".to_owned()),
+            Value::String(":a".to_owned())
+        );
+        assert_ne!(Value::Int(1), Value::Float(1.0));
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let nest = |leaf: Value| {
+            (0..100_000).fold(leaf, |acc, _| {
+                Value::map(vec![Value::Nil, Value::list(vec![acc])])
+            })
+        };
+        let (a, b) = (nest(Value::Nil), nest(Value::Nil));
+        assert_eq!(a, b);
+        assert_ne!(a, nest(Value::Int(1)));
+        let set = Value::set(vec![a.clone(), b]);
+        assert_eq!(set, Value::set(vec![a]));
+    }
+
+    #[test]
+    fn printing() {
+        let v = Value::list(vec![
+            Value::String("a\"b".to_owned()),
+            Value::vector(vec![Value::Keyword("k".to_owned()), Value::Nil]),
+            Value::map(vec![Value::Int(1), Value::Float(2.5)]),
+        ]);
+        assert_eq!(v.pr_str(true), r#"("a\"b" [:k nil] {1 2.5})"#);
+        assert_eq!(v.pr_str(false), r#"(a"b [:k nil] {1 2.5})"#);
+        let empty = Value::vector(vec![Value::list(vec![]), Value::vector(vec![])]);
+        assert_eq!(empty.pr_str(true), "[() []]");
+        let floats = Value::list(
+            [
+                1.0,
+                -0.0,
+                0.25,
+                1e20,
+                f64::INFINITY,
+                -f64::INFINITY,
+                f64::NAN,
+            ]
+            .iter()
+            .map(|&x| Value::Float(x))
+            .collect(),
+        );
+        assert_eq!(
+            floats.pr_str(true),
+            "(1.0 -0.0 0.25 100000000000000000000.0 ##Inf ##-Inf ##NaN)"
+        );
+    }
+
+    #[test]
+    fn map_keys() {
+        let one = || vec![Value::Int(1)];
+        let map = Value::map(vec![Value::list(one()), Value::Nil]);
+        let entries = match &map {
+            Value::Map(m, _) => m,
+            _ => unreachable!(),
+        };
+        assert!(entries.contains_key(&Value::vector(one())));
+        assert!(!entries.contains_key(&Value::Float(1.0)));
+        let nested = |m| Value::map(vec![Value::Int(0), m]);
+        assert_eq!(
+            nested(Value::map(vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Nil,
+                Value::Nil
+            ])),
+            nested(Value::map(vec![
+                Value::Nil,
+                Value::Nil,
+                Value::Int(1),
+                Value::Int(2)
+            ]))
+        );
+    }
+
+    #[test]
+    fn sets() {
+        let set = |values: Vec<i64>| Value::set(values.into_iter().map(Value::Int).collect());
+        assert_eq!(set(vec![1, 2, 1]), set(vec![2, 1]));
+        assert_ne!(set(vec![1]), Value::vector(vec![Value::Int(1)]));
+        assert_eq!(set(vec![1, 1]).pr_str(true), "#{1}");
+        let keyed = Value::map(vec![set(vec![1, 2]), Value::Nil]);
+        assert_eq!(keyed, Value::map(vec![set(vec![2, 1]), Value::Nil]));
+    }
+
+    #[test]
+    fn metadata() {
+        let v = Value::vector(vec![Value::Int(1)]);
+        let tagged = v.with_meta(Value::Int(2)).unwrap();
+        assert_eq!(tagged.meta(), Value::Int(2));
+        assert_eq!(v.meta(), Value::Nil);
+        assert_eq!(tagged, v);
+        assert!(Value::Int(1).with_meta(Value::Nil).is_err());
+    }
+
+    #[test]
+    fn closure_binding() {
+        let closure = Closure {
+            params: vec!["a".to_owned()],
+            rest: Some("more".to_owned()),
+            body: Ast::nil(),
+            env: Env::new(None),
+            eval: |_, _| Ok(Value::Nil),
+            is_macro: false,
+            meta: None,
+        };
+        assert_eq!(closure.arity(), Arity::at_least(1));
+        let env = closure.bind(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        assert_eq!(env.borrow().search("a"), Ok(Value::Int(1)));
+        assert_eq!(
+            env.borrow().search("more"),
+            Ok(Value::list(vec![Value::Int(2), Value::Int(3)]))
+        );
+    }
+
+    #[test]
+    fn code_round_trip() {
+        let forms = crate::reader::read_all("(f [1 :k] {\"a\" nil} #{2})").unwrap();
+        let value = Value::from_ast(&forms[0]).unwrap();
+        assert_eq!(value.pr_str(true), "(f [1 :k] {\"a\" nil} #{2})");
+        assert_eq!(value.to_ast(Span::default()), Ok(forms[0].clone()));
+        let f = Value::builtin(Arity::exactly(0), |_| Ok(Value::Nil));
+        let embedded = Value::list(vec![f, Value::atom(Value::Int(1))]);
+        let ast = embedded.to_ast(Span::default()).unwrap();
+        assert_eq!(ast.to_string(), "(#<function> (atom 1))");
+        assert_eq!(Value::from_ast(&ast), Ok(embedded));
+    }
+}